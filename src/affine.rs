@@ -0,0 +1,225 @@
+use crate::bit_map::{Move, Path};
+use crate::segment::Vec2;
+
+/// an affine transform of the plane, the point `(x, y)` is mapped to
+/// `(m[0][0] * x + m[0][1] * y + m[0][2], m[1][0] * x + m[1][1] * y + m[1][2])`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Affine {
+    pub m : [[f64; 3]; 2]
+}
+
+impl Affine {
+    pub fn identity() -> Self {
+        Affine{m:[[1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]}
+    }
+
+    pub fn translation(dx:f64, dy:f64) -> Self {
+        Affine{m:[[1.0, 0.0, dx], [0.0, 1.0, dy]]}
+    }
+
+    /// rotation of `angle` radian counter-clockwise around `center`
+    pub fn rotation(angle:f64, center:Vec2) -> Self {
+        let (s, c) = angle.sin_cos();
+        let (x, y) = (center.get_x(), center.get_y());
+        Affine{m:[
+            [c, -s, x - c * x + s * y],
+            [s, c, y - s * x - c * y]
+        ]}
+    }
+
+    /// scaling by `sx` along the x-axis and `sy` along the y-axis, `center` is fixed
+    pub fn scaling(sx:f64, sy:f64, center:Vec2) -> Self {
+        let (x, y) = (center.get_x(), center.get_y());
+        Affine{m:[[sx, 0.0, x - sx * x], [0.0, sy, y - sy * y]]}
+    }
+
+    /// return the transform applying `self` then `other`
+    pub fn then(&self, other:&Affine) -> Self {
+        let (a, b) = (other.m, self.m);
+        let mut m = [[0.0; 3]; 2];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+            }
+            row[2] += a[i][2];
+        }
+        Affine{m}
+    }
+
+    pub fn apply(&self, p:Vec2) -> Vec2 {
+        let m = self.m;
+        Vec2::new(
+            m[0][0] * p.get_x() + m[0][1] * p.get_y() + m[0][2],
+            m[1][0] * p.get_x() + m[1][1] * p.get_y() + m[1][2]
+        )
+    }
+
+    /// return `true` if the transform reverses the orientation, turning the climb
+    /// milling of a path into a conventional milling
+    pub fn is_mirror(&self) -> bool {
+        self.m[0][0] * self.m[1][1] - self.m[0][1] * self.m[1][0] < 0.0
+    }
+}
+
+/// a step-and-repeat layout of copies of a path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Array {
+    /// `columns * rows` copies, the copy `(i, j)` is translated by `(i * spacing.0, j * spacing.1)`
+    Rectangular{columns:usize, rows:usize, spacing:(f64, f64)},
+    /// `count` copies, the copy `k` is rotated by `k * angle` radian around `center`
+    Circular{count:usize, center:(f64, f64), angle:f64}
+}
+
+impl Array {
+    /// `count` copies evenly spread on a full turn around `center`
+    pub fn circle(count:usize, center:(f64, f64)) -> Self {
+        Array::Circular{count, center, angle:2.0 * std::f64::consts::PI / usize::max(1, count) as f64}
+    }
+
+    /// return the transform of each copy in the order they are cut, the rows of a
+    /// rectangular array are run back and forth to shorten the rapid moves
+    pub fn transforms(&self) -> Vec<Affine> {
+        match *self {
+            Array::Rectangular{columns, rows, spacing} => (0..rows).flat_map(|j| {
+                let columns : Vec<usize> = if j % 2 == 0 {(0..columns).collect()} else {(0..columns).rev().collect()};
+                columns.into_iter().map(move |i| Affine::translation(i as f64 * spacing.0, j as f64 * spacing.1))
+            }).collect(),
+            Array::Circular{count, center, angle} => (0..count)
+                .map(|k| Affine::rotation(k as f64 * angle, Vec2::new(center.0, center.1)))
+                .collect()
+        }
+    }
+}
+
+impl Path {
+    /// return the path with all its horizontal positions transformed by `transform`,
+    /// the heights are unchanged
+    pub fn transformed(&self, transform:&Affine) -> Path {
+        let map = |x:f64, y:f64| {
+            let p = transform.apply(Vec2::new(x, y));
+            (p.get_x(), p.get_y())
+        };
+        let (x_init, y_init) = map(self.x_init, self.y_init);
+
+        let path = self.path.iter().map(|m| match *m {
+            Move::XYmove(x, y) => {let (x, y) = map(x, y); Move::XYmove(x, y)},
+            Move::FXYmove(x, y) => {let (x, y) = map(x, y); Move::FXYmove(x, y)},
+            Move::XYZmove(x, y, z) => {let (x, y) = map(x, y); Move::XYZmove(x, y, z)},
+            Move::Zmove(z) => Move::Zmove(z),
+            Move::Feed(speed) => Move::Feed(speed)
+        }).collect();
+
+        Path{x_init, y_init, z_init:self.z_init, path}
+    }
+
+    pub fn translated(&self, dx:f64, dy:f64) -> Path {
+        self.transformed(&Affine::translation(dx, dy))
+    }
+
+    /// rotate the path by `angle` radian counter-clockwise around `center`
+    pub fn rotated(&self, angle:f64, center:Vec2) -> Path {
+        self.transformed(&Affine::rotation(angle, center))
+    }
+
+    /// scale the path by `sx` along the x-axis and `sy` along the y-axis, `center` is fixed
+    pub fn scaled(&self, sx:f64, sy:f64, center:Vec2) -> Path {
+        self.transformed(&Affine::scaling(sx, sy, center))
+    }
+
+    /// reverse the path along the x-axis around the vertical line at `x`,
+    /// the climb milling becomes a conventional milling
+    pub fn mirrored_x(&self, x:f64) -> Path {
+        self.scaled(-1.0, 1.0, Vec2::new(x, 0.0))
+    }
+
+    /// reverse the path along the y-axis around the horizontal line at `y`,
+    /// the climb milling becomes a conventional milling
+    pub fn mirrored_y(&self, y:f64) -> Path {
+        self.scaled(1.0, -1.0, Vec2::new(0.0, y))
+    }
+
+    /// return the position of the tool at the end of the path
+    pub fn end(&self) -> (f64, f64, f64) {
+        self.path.iter().fold((self.x_init, self.y_init, self.z_init), |(x, y, z), m| match *m {
+            Move::XYmove(nx, ny) | Move::FXYmove(nx, ny) => (nx, ny, z),
+            Move::Zmove(nz) => (x, y, nz),
+            Move::XYZmove(nx, ny, nz) => (nx, ny, nz),
+            Move::Feed(_) => (x, y, z)
+        })
+    }
+
+    /// return the copies of the path laid out by `array` in one path, the tool rises
+    /// to `fly_z` between two copies and reaches the initial position of the next copy
+    /// with a rapid move, the initial height of the path must be above the stock
+    pub fn repeated(&self, array:&Array, fly_z:f64) -> Path {
+        let mut copies = array.transforms().into_iter().map(|t| self.transformed(&t));
+        let mut out = match copies.next() {
+            Some(first) => first,
+            None => return Path{x_init:self.x_init, y_init:self.y_init, z_init:self.z_init, path:vec![]}
+        };
+
+        for copy in copies {
+            let (_, _, z) = out.end();
+            if z < fly_z {out.path.push(Move::Zmove(fly_z));}
+            out.path.push(Move::FXYmove(copy.x_init, copy.y_init));
+            if copy.z_init != f64::max(z, fly_z) {out.path.push(Move::Zmove(copy.z_init));}
+            out.path.extend(copy.path);
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::affine::*;
+
+    fn close(a:(f64, f64), b:(f64, f64)) -> bool {
+        f64::abs(a.0 - b.0) < 1e-12 && f64::abs(a.1 - b.1) < 1e-12
+    }
+
+    #[test]
+    fn test_transforms() {
+        let t = Affine::rotation(std::f64::consts::FRAC_PI_2, Vec2::new(1.0, 1.0))
+            .then(&Affine::translation(2.0, 0.0));
+        let p = t.apply(Vec2::new(2.0, 1.0));
+        assert!(close((p.get_x(), p.get_y()), (3.0, 2.0)));
+        assert!(!t.is_mirror());
+
+        let path = Path{x_init:0.0, y_init:0.0, z_init:1.0, path:vec![
+            Move::FXYmove(1.0, 2.0),
+            Move::Zmove(-1.0),
+            Move::XYmove(3.0, 2.0),
+            Move::Zmove(1.0)
+        ]};
+        let mirrored = path.mirrored_x(1.0);
+        assert_eq!(mirrored.path[2], Move::XYmove(-1.0, 2.0));
+        assert_eq!(mirrored.path[1], Move::Zmove(-1.0));
+        assert_eq!(path.scaled(2.0, 3.0, Vec2::new(1.0, 2.0)).path[2], Move::XYmove(5.0, 2.0));
+        assert!(Affine::scaling(1.0, -1.0, Vec2::new(0.0, 0.0)).is_mirror());
+    }
+
+    #[test]
+    fn test_arrays() {
+        let path = Path{x_init:0.0, y_init:0.0, z_init:1.0, path:vec![
+            Move::FXYmove(1.0, 0.0),
+            Move::Zmove(-1.0),
+            Move::XYmove(2.0, 0.0)
+        ]};
+
+        // 3 * 2 copies, the second row is run backward
+        let grid = path.repeated(&Array::Rectangular{columns:3, rows:2, spacing:(10.0, 5.0)}, 1.0);
+        let starts : Vec<Move> = grid.path.iter().filter(|m| matches!(m, Move::FXYmove(_, _))).copied().collect();
+        assert_eq!(starts.len(), 11);
+        assert_eq!(grid.path.iter().filter(|m| **m == Move::Zmove(-1.0)).count(), 6);
+        assert_eq!(grid.path[3], Move::Zmove(1.0));
+        assert_eq!(grid.path[4], Move::FXYmove(10.0, 0.0));
+        assert_eq!(starts[5], Move::FXYmove(20.0, 5.0));
+        assert_eq!(grid.end(), (2.0, 5.0, -1.0));
+
+        // 4 copies around the origin
+        let circle = path.repeated(&Array::circle(4, (0.0, 0.0)), 1.0);
+        let (x, y, _) = circle.end();
+        assert!(close((x, y), (0.0, -2.0)));
+    }
+}
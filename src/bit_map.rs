@@ -1,19 +1,26 @@
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
 use crate::height_map::*;
 use image::{RgbImage, DynamicImage, Pixel, Luma, ImageFormat::Png};
+use rayon::prelude::*;
 
+/// a map of booleans, packed in words of 64 bits: each column of the
+/// map starts at a new word, the pixel `(x, y)` is the bit `y % 64` of the
+/// word `x * words + y / 64`, the unused bits of the last word of a column are zero
 pub struct BitMap {
     height: usize,
     width : usize,
-    buffer: Vec<bool>
+    words : usize,
+    buffer: Vec<u64>
 }
 
 impl BitMap {
 
     pub fn new(width: usize, height : usize) -> Self {
-        let mut buffer = Vec::with_capacity(width * height);
-        for _ in 0..width*height {buffer.push(false);}
+        let words = height.div_ceil(64);
+        let buffer = vec![0; width * words];
 
-        BitMap {width, height, buffer}
+        BitMap {width, height, words, buffer}
     }
 
     pub fn from_height_map(hmap:&HeightMap, max_val:f64) -> Self {
@@ -28,8 +35,13 @@ impl BitMap {
         out
     }
 
+    pub fn get_height(&self) -> usize {self.height}
+    pub fn get_width(&self) -> usize {self.width}
+
     pub fn unsafe_set(&mut self, x:usize, y:usize, val:bool) -> &mut Self {
-        self.buffer[x * self.height + y] = val;
+        let word = &mut self.buffer[x * self.words + y / 64];
+        if val {*word |= 1 << (y % 64);}
+        else {*word &= !(1 << (y % 64));}
         self
     }
 
@@ -39,7 +51,7 @@ impl BitMap {
     }
 
     pub fn unsafe_get(&self, x:usize, y:usize) -> bool {
-        self.buffer[x * self.height + y]
+        (self.buffer[x * self.words + y / 64] >> (y % 64)) & 1 == 1
     }
 
     pub fn get(&self, x:usize, y:usize) -> bool {
@@ -52,6 +64,163 @@ impl BitMap {
         else {false}
     }
 
+    /// return the number of pixels set to `true`
+    pub fn count(&self) -> usize {
+        self.buffer.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// mask of the used bits of the last word of a column
+    fn last_mask(&self) -> u64 {
+        match self.height % 64 {
+            0 => !0,
+            r => (1 << r) - 1
+        }
+    }
+
+    /// return the words of the column `x`
+    fn column(&self, x:usize) -> &[u64] {
+        &self.buffer[x * self.words..(x+1) * self.words]
+    }
+
+    /// set `out[y] = col[y + k]` in a column of `height` bits, the bits
+    /// shifted from outside of the column are `false`
+    fn shift_column(col:&[u64], k:isize, height:usize, out:&mut [u64]) {
+        let n = col.len();
+        let (q, r) = (k.unsigned_abs() / 64, k.unsigned_abs() % 64);
+        let get = |i:isize| if i >= 0 && (i as usize) < n {col[i as usize]} else {0};
+
+        for (i, o) in out.iter_mut().enumerate() {
+            let i = i as isize;
+            let q = q as isize;
+            *o = if k >= 0 {
+                let high = if r == 0 {0} else {get(i + q + 1) << (64 - r)};
+                (get(i + q) >> r) | high
+            } else {
+                let low = if r == 0 {0} else {get(i - q - 1) >> (64 - r)};
+                (get(i - q) << r) | low
+            };
+        }
+
+        if !height.is_multiple_of(64) {
+            out[n-1] &= (1 << (height % 64)) - 1;
+        }
+    }
+
+    /// combine with `op` the shifts of a column by `0..len` if `dir` is `1`,
+    /// or by `-len+1..=0` if `dir` is `-1`, the bits outside of the column are `false`
+    fn directed_window(col:&[u64], len:usize, dir:isize, height:usize, op:fn(u64, u64) -> u64) -> Vec<u64> {
+        let mut tmp = vec![0; col.len()];
+
+        // `g[y]` combines the window `[y, y + size)` (in the direction `dir`),
+        // `acc[y]` the window `[y, y + pos)`
+        let mut g = col.to_vec();
+        let mut acc : Option<Vec<u64>> = None;
+        let (mut size, mut pos, mut remaining) = (1, 0, len);
+
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                acc = Some(match acc {
+                    None => g.clone(),
+                    Some(mut a) => {
+                        Self::shift_column(&g, dir * pos as isize, height, &mut tmp);
+                        a.iter_mut().zip(tmp.iter()).for_each(|(a, t)| *a = op(*a, *t));
+                        a
+                    }
+                });
+                pos += size;
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                Self::shift_column(&g, dir * size as isize, height, &mut tmp);
+                g.iter_mut().zip(tmp.iter()).for_each(|(g, t)| *g = op(*g, *t));
+                size *= 2;
+            }
+        }
+
+        acc.unwrap()
+    }
+
+    /// combine with `op` the shifts of a column by `-h..=h`, `out[y] = op(col[y-h], ..., col[y+h])`,
+    /// the bits outside of the column are `false`
+    fn window_column(col:&[u64], h:usize, height:usize, op:fn(u64, u64) -> u64, out:&mut [u64]) {
+        let up = Self::directed_window(col, h + 1, 1, height, op);
+        let down = Self::directed_window(col, h + 1, -1, height, op);
+        out.iter_mut().zip(up.iter().zip(down.iter())).for_each(|(o, (u, d))| *o = op(*u, *d));
+    }
+
+    /// combine with `op` the pixels of the disk of radius `radius` (in pixels)
+    /// around each pixel, the pixels outside of the map are `false`
+    fn morphology(&self, radius:f64, op:fn(u64, u64) -> u64, neutral:u64) -> Self {
+        assert!(radius >= 0.0);
+
+        let mut out = Self::new(self.width, self.height);
+        if self.words == 0 {return out;}
+
+        let r = radius.floor() as isize;
+        let half_heights : Vec<(isize, usize)> = (-r..=r)
+            .map(|dx| (dx, f64::sqrt(radius * radius - (dx * dx) as f64).floor() as usize))
+            .collect();
+
+        let (width, height, words) = (self.width, self.height, self.words);
+        let mask = self.last_mask();
+
+        out.buffer.par_chunks_mut(words).enumerate().for_each(|(x, col)| {
+            let mut tmp = vec![0; words];
+            col.iter_mut().for_each(|w| *w = neutral);
+
+            for (dx, h) in half_heights.iter() {
+                let source = x as isize + dx;
+                if source < 0 || source >= width as isize {
+                    // a column outside of the map is `false`
+                    tmp.iter_mut().for_each(|w| *w = 0);
+                } else {
+                    Self::window_column(self.column(source as usize), *h, height, op, &mut tmp);
+                }
+                col.iter_mut().zip(tmp.iter()).for_each(|(c, t)| *c = op(*c, *t));
+            }
+
+            col[words-1] &= mask;
+        });
+
+        out
+    }
+
+    /// return the dilation of the map by a disk of radius `radius` in pixels:
+    /// a pixel is `true` if a `true` pixel is in the disk around it,
+    /// use `tool.get_rayon() / pixel_size` to grow a region by the radius of a tool
+    pub fn dilate(&self, radius:f64) -> Self {
+        self.morphology(radius, |a, b| a | b, 0)
+    }
+
+    /// return the erosion of the map by a disk of radius `radius` in pixels:
+    /// a pixel is `true` if all the pixels in the disk around it are `true`,
+    /// the pixels outside of the map are `false`,
+    /// use `tool.get_rayon() / pixel_size` to shrink a region by the radius of a tool
+    pub fn erode(&self, radius:f64) -> Self {
+        self.morphology(radius, |a, b| a & b, !0)
+    }
+
+    /// return the opening of the map (an erosion followed by a dilation),
+    /// it removes the regions thinner than the disk of radius `radius`
+    pub fn open(&self, radius:f64) -> Self {
+        self.erode(radius).dilate(radius)
+    }
+
+    /// return the closing of the map (a dilation followed by an erosion),
+    /// it fills the holes thinner than the disk of radius `radius`
+    pub fn close(&self, radius:f64) -> Self {
+        self.dilate(radius).erode(radius)
+    }
+
+    /// combine two maps of the same size word by word
+    fn zip_with(&self, other:&Self, op:fn(u64, u64) -> u64) -> Self {
+        assert!(self.width == other.width && self.height == other.height);
+        BitMap{
+            width:self.width, height:self.height, words:self.words,
+            buffer:self.buffer.iter().zip(other.buffer.iter()).map(|(a, b)| op(*a, *b)).collect()
+        }
+    }
+
     pub fn save(&self, path:&str) -> Result<(), String> {
         let mut rgb: RgbImage = RgbImage::new(self.width as u32, self.height as u32);
 
@@ -62,7 +231,7 @@ impl BitMap {
             }
         }
 
-        if let Err(_) = DynamicImage::ImageRgb8(rgb).save_with_format(path, Png){
+        if DynamicImage::ImageRgb8(rgb).save_with_format(path, Png).is_err() {
             Err("unable to save the image".to_string())
         } else {Ok(())}
 
@@ -70,14 +239,49 @@ impl BitMap {
 
 }
 
-#[derive(Clone, Copy)]
+impl BitAnd for &BitMap {
+    type Output = BitMap;
+    fn bitand(self, other:Self) -> BitMap {
+        self.zip_with(other, |a, b| a & b)
+    }
+}
+
+impl BitOr for &BitMap {
+    type Output = BitMap;
+    fn bitor(self, other:Self) -> BitMap {
+        self.zip_with(other, |a, b| a | b)
+    }
+}
+
+impl BitXor for &BitMap {
+    type Output = BitMap;
+    fn bitxor(self, other:Self) -> BitMap {
+        self.zip_with(other, |a, b| a ^ b)
+    }
+}
+
+impl Not for &BitMap {
+    type Output = BitMap;
+    fn not(self) -> BitMap {
+        let mut out = self.zip_with(self, |a, _| !a);
+        let mask = self.last_mask();
+        if out.words > 0 {
+            out.buffer.chunks_mut(self.words).for_each(|col| col[self.words-1] &= mask);
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Move{
     XYmove(f64, f64), // move to the position `x, y, same_z_as_current`
     Zmove(f64), // move to the position `same_x_as_current, same_y_as_current, z`
-    FXYmove(f64, f64) // move fast to the position `x, y, same_z_as_current`
+    FXYmove(f64, f64), // move fast to the position `x, y, same_z_as_current`
+    XYZmove(f64, f64, f64), // move in a straight line to the position `x, y, z`
+    Feed(f64) // set the speed in `m / s` of the next working moves, until the next `Feed`
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Path {
     pub x_init : f64,
     pub y_init : f64,
@@ -86,5 +290,87 @@ pub struct Path {
 }
 
 pub trait PathAlgo {
+    #[allow(clippy::wrong_self_convention)]
     fn from_bit_map(&self, bit_map:&BitMap, x_init:f64, y_init:f64, z_init:f64) -> Path;
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::bit_map::*;
+
+    /// naive dilation or erosion by a disk, for comparison
+    fn naive(bmap:&BitMap, radius:f64, dilate:bool) -> BitMap {
+        let mut out = BitMap::new(bmap.get_width(), bmap.get_height());
+        let r = radius.floor() as isize;
+        for x in 0..bmap.get_width() as isize {
+            for y in 0..bmap.get_height() as isize {
+                let mut val = !dilate;
+                for dx in -r..=r {
+                    for dy in -r..=r {
+                        if ((dx * dx + dy * dy) as f64) > radius * radius {continue;}
+                        let (i, j) = (x + dx, y + dy);
+                        let p = i >= 0 && j >= 0 && bmap.get_default(i as usize, j as usize);
+                        if dilate {val |= p;} else {val &= p;}
+                    }
+                }
+                out.set(x as usize, y as usize, val);
+            }
+        }
+        out
+    }
+
+    fn same(b1:&BitMap, b2:&BitMap) -> bool {
+        (0..b1.get_width()).all(|i| (0..b1.get_height()).all(|j| b1.get(i, j) == b2.get(i, j)))
+    }
+
+    #[test]
+    fn test_packing() {
+        let mut bmap = BitMap::new(3, 130);
+        bmap.set(1, 63, true).set(1, 64, true).set(2, 129, true);
+        assert!(bmap.get(1, 63) && bmap.get(1, 64) && bmap.get(2, 129));
+        assert!(!bmap.get(1, 62) && !bmap.get(1, 65) && !bmap.get(2, 0));
+        assert_eq!(bmap.count(), 3);
+
+        bmap.set(1, 64, false);
+        assert_eq!(bmap.count(), 2);
+        assert_eq!((!&bmap).count(), 3 * 130 - 2);
+    }
+
+    #[test]
+    fn test_morphology() {
+        let mut rng = rand::thread_rng();
+        use rand::Rng;
+
+        let mut bmap = BitMap::new(17, 150);
+        for x in 0..17 {
+            for y in 0..150 {
+                bmap.set(x, y, rng.gen_bool(0.7));
+            }
+        }
+
+        for radius in [0.0, 1.0, 1.5, 2.9, 4.0] {
+            assert!(same(&bmap.dilate(radius), &naive(&bmap, radius, true)));
+            assert!(same(&bmap.erode(radius), &naive(&bmap, radius, false)));
+        }
+
+        // the opening removes an isolated pixel, the closing fills a hole
+        let mut speck = BitMap::new(10, 10);
+        speck.set(5, 5, true);
+        assert_eq!(speck.open(1.0).count(), 0);
+        assert_eq!(speck.dilate(1.0).count(), 5);
+        assert!((!&speck).close(1.0).get(5, 5));
+    }
+
+    #[test]
+    fn test_boolean() {
+        let mut b1 = BitMap::new(2, 70);
+        let mut b2 = BitMap::new(2, 70);
+        b1.set(0, 0, true).set(1, 69, true);
+        b2.set(1, 69, true).set(0, 68, true);
+
+        assert_eq!((&b1 & &b2).count(), 1);
+        assert_eq!((&b1 | &b2).count(), 3);
+        assert_eq!((&b1 ^ &b2).count(), 2);
+        assert!((&b1 ^ &b2).get(0, 68));
+    }
+}
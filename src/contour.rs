@@ -0,0 +1,689 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use rayon::prelude::*;
+
+use crate::bit_map::{BitMap, Move, Path, PathAlgo};
+use crate::coordinates::{CoordinateMap, Placement};
+use crate::height_map::HeightMap;
+use crate::parse_config::{MillingDirection, Tabs, TabPlacement};
+use crate::region::Connectivity;
+use crate::segment::{orient_regions, Case2, Polygon, Segment, Vec2};
+
+/// key of a point of the marching squares, its coordinates are multiples of `0.5`
+fn key(p:Vec2) -> (i64, i64) {
+    ((2.0 * p.get_x()).round() as i64, (2.0 * p.get_y()).round() as i64)
+}
+
+/// key of an interpolated point of the marching squares, the points of the cells
+/// sharing an edge are exactly equal
+fn exact_key(p:Vec2) -> (u64, u64) {
+    (p.get_x().to_bits(), p.get_y().to_bits())
+}
+
+/// remove the vertices of a closed polyline aligned with their neighbours
+fn remove_collinear(points:Vec<Vec2>) -> Vec<Vec2> {
+    let n = points.len();
+    let mut out : Vec<Vec2> = vec![];
+
+    for i in 0..n {
+        let prev = out.last().copied().unwrap_or(points[n-1]);
+        let next = points[(i+1) % n];
+        if (points[i] - prev).cross(next - points[i]) != 0.0 {out.push(points[i]);}
+    }
+
+    out
+}
+
+/// chain undirected segments sharing their endpoints into closed loops,
+/// each endpoint is shared by two segments, the endpoints with the same `key` are equal
+fn chain_segments<K:Hash + Eq>(segments:&[Segment], key:impl Fn(Vec2) -> K) -> Vec<Polygon> {
+    let mut ends : HashMap<K, Vec<usize>> = HashMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        ends.entry(key(s.source())).or_default().push(i);
+        ends.entry(key(s.target())).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut loops = vec![];
+
+    for first in 0..segments.len() {
+        if used[first] {continue;}
+        used[first] = true;
+
+        let start = segments[first].source();
+        let mut points = vec![start];
+        let mut current = segments[first].target();
+
+        while key(current) != key(start) {
+            points.push(current);
+            let next = match ends[&key(current)].iter().copied().find(|i| !used[*i]) {
+                Some(next) => next,
+                None => break
+            };
+            used[next] = true;
+
+            let s = segments[next];
+            current = if key(s.source()) == key(current) {s.target()} else {s.source()};
+        }
+
+        let points = remove_collinear(points);
+        if points.len() >= 3 {loops.push(Polygon::new(points));}
+    }
+
+    loops
+}
+
+/// the endpoints `(segment, end)` linked to the source and the target of a segment
+type Links = [Option<(usize, usize)>; 2];
+
+/// return the endpoint `end` of a segment, `0` its source and `1` its target
+fn endpoint(s:&Segment, end:usize) -> Vec2 {
+    if end == 0 {s.source()} else {s.target()}
+}
+
+/// link the endpoints sharing a key by pairs, `(segment, end)` is linked to `neighbours[segment - offset][end]`,
+/// return the endpoints left alone
+fn link_ends<K:Hash + Eq>(ends:HashMap<K, Vec<(usize, usize)>>, neighbours:&mut [Links], offset:usize)
+    -> Vec<(K, usize, usize)> {
+    let mut alone = vec![];
+    for (k, list) in ends {
+        if let [(i, a)] = list[..] {
+            alone.push((k, i, a));
+            continue;
+        }
+        for pair in list.chunks_exact(2) {
+            let ((i, a), (j, b)) = (pair[0], pair[1]);
+            neighbours[i - offset][a] = Some((j, b));
+            neighbours[j - offset][b] = Some((i, a));
+        }
+    }
+    alone
+}
+
+/// like `chain_segments` with the segments linked in parallel by strips of consecutive
+/// segments, then stitched across the borders of the strips, the result is the same
+/// as `chain_segments` when each endpoint is shared by two segments
+fn par_chain_segments<K:Hash + Eq + Send>(segments:&[Segment], key:impl Fn(Vec2) -> K + Sync) -> Vec<Polygon> {
+    let n = segments.len();
+    // a few strips by thread to balance them
+    let strip = usize::max(1, n.div_ceil(4 * rayon::current_num_threads()));
+
+    let strips : Vec<_> = (0..n).step_by(strip)
+        .collect::<Vec<usize>>()
+        .into_par_iter()
+        .map(|lo| {
+            let hi = usize::min(lo + strip, n);
+            let mut ends : HashMap<K, Vec<(usize, usize)>> = HashMap::new();
+            for (i, s) in segments.iter().enumerate().take(hi).skip(lo) {
+                ends.entry(key(s.source())).or_default().push((i, 0));
+                ends.entry(key(s.target())).or_default().push((i, 1));
+            }
+            let mut neighbours = vec![[None; 2]; hi - lo];
+            let border = link_ends(ends, &mut neighbours, lo);
+            (neighbours, border)
+        })
+        .collect();
+
+    // stitch the endpoints on the borders of the strips
+    let mut neighbours = vec![];
+    let mut ends : HashMap<K, Vec<(usize, usize)>> = HashMap::new();
+    for (strip_neighbours, border) in strips {
+        neighbours.extend(strip_neighbours);
+        for (k, i, end) in border {
+            ends.entry(k).or_default().push((i, end));
+        }
+    }
+    link_ends(ends, &mut neighbours, 0);
+
+    // follow the links in the order of the sequential chaining
+    let mut used = vec![false; n];
+    let mut loops = vec![];
+    for first in 0..n {
+        if used[first] {continue;}
+        used[first] = true;
+
+        let mut points = vec![segments[first].source()];
+        let (mut s, mut end) = (first, 1);
+        loop {
+            let next = neighbours[s][end];
+            if next == Some((first, 0)) {break;}
+            points.push(endpoint(&segments[s], end));
+            match next {
+                Some((t, entry)) if !used[t] => {
+                    used[t] = true;
+                    (s, end) = (t, 1 - entry);
+                },
+                _ => break
+            }
+        }
+        loops.push(points);
+    }
+
+    loops.into_par_iter()
+        .map(remove_collinear)
+        .filter(|points| points.len() >= 3)
+        .map(Polygon::new)
+        .collect()
+}
+
+/// return the segments of the marching squares of the cells from `(-1, -1)` to
+/// `(width - 1, height - 1)` given by `cell`, column by column
+fn cell_segments(width:usize, height:usize, cell:impl Fn(isize, isize) -> Case2<Segment>) -> Vec<Segment> {
+    let mut segments = vec![];
+    for x in -1..width as isize {
+        push_column(x, height, &cell, &mut segments);
+    }
+    segments
+}
+
+/// like `cell_segments` with the columns computed in parallel, in the same order
+fn par_cell_segments(width:usize, height:usize, cell:impl Fn(isize, isize) -> Case2<Segment> + Sync) -> Vec<Segment> {
+    (-1..width as isize).into_par_iter()
+        .map(|x| {
+            let mut column = vec![];
+            push_column(x, height, &cell, &mut column);
+            column
+        })
+        .collect::<Vec<Vec<Segment>>>()
+        .concat()
+}
+
+/// push the segments of the cells of the column `x`
+fn push_column(x:isize, height:usize, cell:&impl Fn(isize, isize) -> Case2<Segment>, segments:&mut Vec<Segment>) {
+    for y in -1..height as isize {
+        match cell(x, y) {
+            Case2::C0 => {},
+            Case2::C1(s) => segments.push(s),
+            Case2::C2(s1, s2) => {
+                segments.push(s1);
+                segments.push(s2);
+            }
+        }
+    }
+}
+
+impl BitMap {
+    /// return the boundaries of the regions of `true` pixels as closed loops chained
+    /// from the segments of the marching squares, in pixel coordinates (the pixel `(i, j)`
+    /// is at `(i as f64, j as f64)`), the pixels outside of the map are `false`, the
+    /// regions are 4-connected
+    pub fn contours(&self) -> Vec<Polygon> {
+        self.connected_contours(Connectivity::Four)
+    }
+
+    /// like `contours` with the regions connected with `connectivity`, the same as the
+    /// labelling of the regions: two `true` pixels touching only by a corner are in one
+    /// loop with the 8-connectivity, the loops never cross
+    pub fn connected_contours(&self, connectivity:Connectivity) -> Vec<Polygon> {
+        let segments = cell_segments(self.get_width(), self.get_height(), |x, y| self.from_pixel_to_segments(x, y, connectivity));
+        chain_segments(&segments, key)
+    }
+
+    /// like `connected_contours` computed in parallel by strips of the map, with the same result
+    pub fn par_connected_contours(&self, connectivity:Connectivity) -> Vec<Polygon> {
+        let segments = par_cell_segments(self.get_width(), self.get_height(), |x, y| self.from_pixel_to_segments(x, y, connectivity));
+        par_chain_segments(&segments, key)
+    }
+}
+
+impl HeightMap {
+    /// return the iso-contours at the height `z`, the boundaries of the regions of pixels
+    /// lower or equal to `z`, as closed loops in pixel coordinates, placed between the
+    /// pixels by a linear interpolation of their heights, the pixels outside of the map
+    /// are above `z`
+    pub fn iso_contours(&self, z:f64) -> Vec<Polygon> {
+        let segments = cell_segments(self.get_width(), self.get_height(), |x, y| self.from_pixel_to_interpolated_segments(x, y, z));
+        chain_segments(&segments, exact_key)
+    }
+
+    /// like `iso_contours` computed in parallel by strips of the map, with the same result
+    pub fn par_iso_contours(&self, z:f64) -> Vec<Polygon> {
+        let segments = par_cell_segments(self.get_width(), self.get_height(), |x, y| self.from_pixel_to_interpolated_segments(x, y, z));
+        par_chain_segments(&segments, exact_key)
+    }
+}
+
+/// orient the boundaries of the regions of `true` pixels, such that the tool cuts in
+/// the direction `direction` when the `true` pixels are the region cut: the boundaries
+/// nested in an even number of loops have the material outside and are counter-clockwise
+/// for a climb milling, the others have the material inside and are clockwise
+/// (with the y-axis pointing up)
+pub fn orient_loops(polygons:&[Polygon], direction:MillingDirection) -> Vec<Polygon> {
+    let oriented = orient_regions(polygons);
+    match direction {
+        MillingDirection::Climb => oriented,
+        MillingDirection::Conventional => oriented.iter().map(|polygon| polygon.reversed()).collect()
+    }
+}
+
+/// a closed polyline parametrized by its arc length, the last point is the first one
+struct Loop {
+    points : Vec<Vec2>,
+    lengths : Vec<f64>
+}
+
+impl Loop {
+    fn new(mut points:Vec<Vec2>) -> Self {
+        points.push(points[0]);
+        let mut lengths = vec![0.0];
+        for w in points.windows(2) {
+            lengths.push(lengths[lengths.len()-1] + w[0].distance(w[1]));
+        }
+        Loop{points, lengths}
+    }
+
+    fn length(&self) -> f64 {self.lengths[self.lengths.len()-1]}
+
+    /// return the point at the arc length `s`, modulo the length of the loop
+    fn point_at(&self, s:f64) -> Vec2 {
+        let s = s.rem_euclid(self.length());
+        let i = self.lengths.partition_point(|l| *l <= s).clamp(1, self.points.len() - 1);
+        let (l0, l1) = (self.lengths[i-1], self.lengths[i]);
+        let t = if l1 > l0 {(s - l0) / (l1 - l0)} else {0.0};
+        self.points[i-1] + (self.points[i] - self.points[i-1]) * t
+    }
+
+    /// return the arc length of the point of the loop closest to `p`, and its distance to `p`
+    fn project(&self, p:Vec2) -> (f64, f64) {
+        let mut best = (0.0, f64::INFINITY);
+        for i in 0..self.points.len() - 1 {
+            let (a, b) = (self.points[i], self.points[i+1]);
+            let len2 = (b - a) * (b - a);
+            let t = if len2 > 0.0 {((p - a) * (b - a) / len2).clamp(0.0, 1.0)} else {0.0};
+            let d = p.distance(a + (b - a) * t);
+            if d < best.1 {best = (self.lengths[i] + t * (self.lengths[i+1] - self.lengths[i]), d);}
+        }
+        best
+    }
+
+    /// return the radius of the circle through the points at `s - h`, `s` and `s + h`,
+    /// an estimation of the radius of curvature of the loop at the scale `h`
+    fn radius_at(&self, s:f64, h:f64) -> f64 {
+        let (p0, p1, p2) = (self.point_at(s - h), self.point_at(s), self.point_at(s + h));
+        let area2 = (p1 - p0).cross(p2 - p0).abs();
+        if area2 < 1e-18 {return f64::INFINITY;}
+        p0.distance(p1) * p1.distance(p2) * p0.distance(p2) / (2.0 * area2)
+    }
+
+    /// return the loop starting at the arc length `s`
+    fn rotated(&self, s:f64) -> Self {
+        let s = s.rem_euclid(self.length());
+        let i = self.lengths.partition_point(|l| *l <= s).clamp(1, self.points.len() - 1);
+
+        let mut points = vec![self.point_at(s)];
+        points.extend(self.points[i..self.points.len()-1].iter().copied());
+        points.extend(self.points[..i].iter().copied());
+        if points[0].distance(points[points.len()-1]) == 0.0 {points.pop();}
+        Loop::new(points)
+    }
+}
+
+/// the contour strategy: follow the boundaries of the regions of `true` pixels
+/// of a bit map, in several passes down to the depth, the inner loops first
+pub struct Contour {
+    /// size in `m` of a pixel along the x-axis and the y-axis
+    pub pixel_size : (f64, f64),
+
+    /// depth in `m` of the cut, the minimum value of z is `-depth`
+    pub depth : f64,
+
+    /// maximum depth in `m` cut by one pass
+    pub pass_depth : f64,
+
+    /// height of the tool during the rapid moves
+    pub fly_z : f64,
+
+    /// tabs left on the last passes, for the through-cuts
+    pub tabs : Option<Tabs>,
+
+    /// cutting direction of the loops, the `true` pixels being the region cut
+    pub direction : Option<MillingDirection>,
+
+    /// connectivity of the regions of `true` pixels
+    pub connectivity : Connectivity,
+
+    /// placement of the paths relative to the bit map, the heights are given from the top of the stock
+    pub placement : Placement
+}
+
+impl Contour {
+    pub fn new(pixel_size:(f64, f64), depth:f64, pass_depth:f64, fly_z:f64) -> Self {
+        Contour{pixel_size, depth, pass_depth, fly_z, tabs:None, direction:None, connectivity:Connectivity::Four, placement:Placement::new()}
+    }
+
+    /// return the depths of the passes, from the top to the bottom
+    fn passes(&self) -> Vec<f64> {
+        let n = f64::max(1.0, (self.depth / self.pass_depth - 1e-9).ceil()) as usize;
+        (1..=n).map(|k| -self.depth * k as f64 / n as f64).collect()
+    }
+
+    /// return the center of the tabs of each loop along its arc length
+    fn place_tabs(&self, tabs:&Tabs, loops:&[Loop]) -> Vec<Vec<f64>> {
+        let w = tabs.width;
+        let mut centers : Vec<Vec<f64>> = vec![vec![]; loops.len()];
+
+        // a tab must not overlap another tab
+        let free = |centers:&[f64], length:f64, s:f64| centers.iter().all(|c| {
+            let d = (s - c).rem_euclid(length);
+            f64::min(d, length - d) >= 2.0 * w
+        });
+
+        if let TabPlacement::Positions(positions) = &tabs.placement {
+            for (x, y) in positions.iter() {
+                let nearest = loops.iter().enumerate()
+                    .map(|(i, l)| (i, l.project(Vec2::new(*x, *y))))
+                    .min_by(|a, b| a.1.1.total_cmp(&b.1.1));
+
+                if let Some((i, (s, _))) = nearest {
+                    if free(&centers[i], loops[i].length(), s) {centers[i].push(s);}
+                }
+            }
+        } else {
+            for (l, out) in loops.iter().zip(centers.iter_mut()) {
+                let length = l.length();
+                let n = match tabs.placement {
+                    TabPlacement::Count(n) => n,
+                    TabPlacement::Spacing(d) => usize::max(1, (length / d).round() as usize),
+                    TabPlacement::Positions(_) => unreachable!()
+                };
+                let n = usize::min(n, (length / (2.0 * w)).floor() as usize);
+
+                // move each tab from its ideal position to the nearest position
+                // where the loop is not too curved
+                let step = f64::max(w / 4.0, 1e-6);
+                for k in 0..n {
+                    let ideal = (k as f64 + 0.5) * length / n as f64;
+                    let range = (0.5 * length / n as f64 / step) as usize;
+
+                    let found = (0..=range)
+                        .flat_map(|i| [ideal + i as f64 * step, ideal - i as f64 * step])
+                        .find(|s| l.radius_at(*s, w) >= tabs.min_radius && free(out, length, *s));
+                    if let Some(s) = found {out.push(s.rem_euclid(length));}
+                }
+            }
+        }
+
+        for c in centers.iter_mut() {c.sort_by(|a, b| a.total_cmp(b));}
+        centers
+    }
+
+    /// push the moves of one pass at the depth `z` around a loop starting at its first point,
+    /// the tool rises to `top` over the intervals `tabs`, sorted along the arc length
+    fn push_pass(l:&Loop, z:f64, top:f64, tabs:&[(f64, f64)], path:&mut Vec<Move>) {
+        let mut events = vec![];
+        for (a, b) in tabs.iter() {
+            events.push((*a, top));
+            events.push((*b, z));
+        }
+
+        let mut last = l.points[0];
+        let mut push_xy = |p:Vec2, path:&mut Vec<Move>| {
+            if p != last {path.push(Move::XYmove(p.get_x(), p.get_y()));}
+            last = p;
+        };
+
+        let mut e = 0;
+        for i in 1..l.points.len() {
+            while e < events.len() && events[e].0 <= l.lengths[i] {
+                push_xy(l.point_at(events[e].0), path);
+                path.push(Move::Zmove(events[e].1));
+                e += 1;
+            }
+            push_xy(l.points[i], path);
+        }
+    }
+}
+
+impl PathAlgo for Contour {
+    fn from_bit_map(&self, bit_map:&BitMap, x_init:f64, y_init:f64, z_init:f64) -> Path {
+        let map = CoordinateMap::new(self.placement, self.pixel_size, (bit_map.get_width(), bit_map.get_height()));
+
+        // the loops are oriented in the coordinates of the paths, a flipped axis reverses them
+        let mut polygons : Vec<Polygon> = bit_map.par_connected_contours(self.connectivity).iter()
+            .map(|polygon| Polygon::new(polygon.points().iter().map(|p| map.to_path(*p)).collect()))
+            .collect();
+        if let Some(direction) = self.direction {
+            polygons = orient_loops(&polygons, direction);
+        }
+        polygons.sort_by(|a, b| a.signed_area().abs().total_cmp(&b.signed_area().abs()));
+
+        let loops : Vec<Loop> = polygons.iter().map(|polygon| Loop::new(polygon.points().to_vec())).collect();
+
+        let centers = match &self.tabs {
+            Some(tabs) => self.place_tabs(tabs, &loops),
+            None => vec![vec![]; loops.len()]
+        };
+        let (width, top) = match &self.tabs {
+            Some(tabs) => (tabs.width, -self.depth + tabs.height),
+            None => (0.0, 0.0)
+        };
+
+        let mut path = vec![Move::Zmove(self.fly_z)];
+
+        for (l, centers) in loops.iter().zip(centers.iter()) {
+            // start the loop in the middle of the largest gap between two tabs
+            let n = centers.len();
+            let start = (0..n).map(|k| {
+                let gap = if n == 1 {l.length()} else {(centers[(k+1) % n] - centers[k]).rem_euclid(l.length())};
+                (centers[k] + gap / 2.0, gap)
+            }).max_by(|a, b| a.1.total_cmp(&b.1)).map(|(s, _)| s).unwrap_or(0.0);
+
+            let mut tabs : Vec<(f64, f64)> = centers.iter().map(|c| {
+                let c = (c - start).rem_euclid(l.length());
+                (c - width / 2.0, c + width / 2.0)
+            }).collect();
+            tabs.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let l = l.rotated(start);
+
+            path.push(Move::FXYmove(l.points[0].get_x(), l.points[0].get_y()));
+            for z in self.passes() {
+                path.push(Move::Zmove(z));
+                let raised = if z < top - 1e-12 {&tabs[..]} else {&[]};
+                Self::push_pass(&l, z, top, raised, &mut path);
+            }
+            path.push(Move::Zmove(self.fly_z));
+        }
+
+        Path{x_init, y_init, z_init, path:map.map_heights(path)}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::contour::*;
+    use rand::Rng;
+
+    fn square(size:usize, from:usize, to:usize) -> BitMap {
+        let mut bmap = BitMap::new(size, size);
+        for x in from..to {
+            for y in from..to {
+                bmap.set(x, y, true);
+            }
+        }
+        bmap
+    }
+
+    #[test]
+    fn test_contours() {
+        // a square with cut corners
+        let contours = square(20, 5, 15).contours();
+        assert_eq!(contours.len(), 1);
+        assert_eq!(contours[0].len(), 8);
+        assert!(f64::abs(contours[0].signed_area().abs() - 99.5) < 1e-9);
+
+        // a ring has an outer loop and an inner loop
+        let mut ring = square(20, 5, 15);
+        for x in 8..12 {
+            for y in 8..12 {
+                ring.set(x, y, false);
+            }
+        }
+        assert_eq!(ring.contours().len(), 2);
+
+        // the pixels touching by a corner are separated, in both diagonals
+        let mut diagonal = BitMap::new(4, 4);
+        diagonal.set(1, 1, true).set(2, 2, true);
+        assert_eq!(diagonal.contours().len(), 2);
+        let mut diagonal = BitMap::new(4, 4);
+        diagonal.set(2, 1, true).set(1, 2, true);
+        assert_eq!(diagonal.contours().len(), 2);
+        assert!(diagonal.contours().iter().all(|c| c.len() == 4));
+
+        // they are in one loop with the 8-connectivity, without crossing
+        let eight = diagonal.connected_contours(Connectivity::Eight);
+        assert_eq!(eight.len(), 1);
+        assert_eq!(eight[0].len(), 4);
+        assert!(simple(&eight[0]));
+        assert!(f64::abs(eight[0].signed_area().abs() - 1.5) < 1e-9);
+    }
+
+    #[test]
+    fn test_par_contours() {
+        let mut rng = rand::thread_rng();
+        let (width, height) = (120, 90);
+
+        let mut bmap = BitMap::new(width, height);
+        let mut heights = vec![0.0; width * height];
+        for x in 0..width {
+            for y in 0..height {
+                bmap.set(x, y, rng.gen_bool(0.45));
+                heights[x + y * width] = f64::sin(x as f64 / 7.0) * f64::cos(y as f64 / 5.0) + rng.gen_range(-0.3..0.3);
+            }
+        }
+        let hmap = HeightMap::new_with_buffer(width, height, heights);
+
+        for connectivity in [Connectivity::Four, Connectivity::Eight] {
+            let contours = bmap.connected_contours(connectivity);
+            assert!(contours.len() > 100);
+            assert_eq!(bmap.par_connected_contours(connectivity), contours);
+        }
+        for z in [-0.5, 0.0, 0.7] {
+            assert_eq!(hmap.par_iso_contours(z), hmap.iso_contours(z));
+        }
+    }
+
+    /// return `true` if the segments of a loop cross only their neighbours, at their ends
+    fn simple(polygon:&Polygon) -> bool {
+        let segments : Vec<Segment> = polygon.segments().collect();
+        let n = segments.len();
+        (0..n).all(|i| (i+2..n).all(|j| (i == 0 && j == n - 1) ||
+            !crate::predicates::segments_intersect(segments[i].source(), segments[i].target(), segments[j].source(), segments[j].target())))
+    }
+
+    #[test]
+    fn test_iso_contours() {
+        // a cone, its iso-contours are circles centered on its top
+        let (size, center) = (41, 20.3);
+        let mut heights = vec![0.0; size * size];
+        for x in 0..size {
+            for y in 0..size {
+                heights[x + y * size] = f64::hypot(x as f64 - center, y as f64 - center);
+            }
+        }
+        let cone = HeightMap::new_with_buffer(size, size, heights);
+
+        let contours = cone.iso_contours(10.0);
+        assert_eq!(contours.len(), 1);
+        let c = Vec2::new(center, center);
+        assert!(contours[0].points().iter().all(|p| f64::abs(p.distance(c) - 10.0) < 0.05));
+        let area = std::f64::consts::PI * 100.0;
+        assert!(f64::abs(contours[0].signed_area().abs() - area) < 0.005 * area);
+
+        // the levels between two pixels move the contour between them
+        let step = HeightMap::new_with_buffer(4, 1, vec![0.0, 0.0, 1.0, 1.0]);
+        for z in [0.25, 0.5, 0.75] {
+            let contours = step.iso_contours(z);
+            assert_eq!(contours.len(), 1);
+            let right = contours[0].points().iter().map(|p| p.get_x()).fold(f64::MIN, f64::max);
+            assert!(f64::abs(right - (1.0 + z)) < 1e-9);
+        }
+
+        // a saddle splits or joins the low pixels with the height of the centre of the cell
+        let saddle = HeightMap::new_with_buffer(2, 2, vec![0.0, 1.0, 1.0, 0.0]);
+        assert_eq!(saddle.iso_contours(0.4).len(), 2);
+        let joined = saddle.iso_contours(0.6);
+        assert_eq!(joined.len(), 1);
+        assert!(simple(&joined[0]));
+    }
+
+    /// return the signed area of each loop cut by a path
+    fn loop_areas(path:&Path) -> Vec<f64> {
+        let mut areas = vec![];
+        let mut points = vec![];
+        for m in path.path.iter() {
+            match m {
+                Move::FXYmove(x, y) => {
+                    if !points.is_empty() {areas.push(Polygon::new(points).signed_area());}
+                    points = vec![Vec2::new(*x, *y)];
+                },
+                Move::XYmove(x, y) => points.push(Vec2::new(*x, *y)),
+                _ => {}
+            }
+        }
+        areas.push(Polygon::new(points).signed_area());
+        areas
+    }
+
+    #[test]
+    fn test_direction() {
+        let mut ring = square(20, 5, 15);
+        for x in 8..12 {
+            for y in 8..12 {
+                ring.set(x, y, false);
+            }
+        }
+
+        // the inner loop is cut first, the material is inside of it
+        let mut contour = Contour::new((1.0, 1.0), 1.0, 1.0, 1.0);
+        contour.direction = Some(MillingDirection::Climb);
+        let areas = loop_areas(&contour.from_bit_map(&ring, 0.0, 0.0, 0.0));
+        assert!(areas[0] < 0.0 && areas[1] > 0.0);
+
+        contour.direction = Some(MillingDirection::Conventional);
+        let areas = loop_areas(&contour.from_bit_map(&ring, 0.0, 0.0, 0.0));
+        assert!(areas[0] > 0.0 && areas[1] < 0.0);
+
+        // the orientation is kept in the coordinates of the paths when an axis is flipped
+        contour.placement.flip_y = true;
+        let path = contour.from_bit_map(&ring, 0.0, 0.0, 0.0);
+        assert!(path.path.iter().all(|m| !matches!(m, Move::XYmove(_, y) if *y > 0.0)));
+        let areas = loop_areas(&path);
+        assert!(areas[0] > 0.0 && areas[1] < 0.0);
+    }
+
+    #[test]
+    fn test_tabs() {
+        let bmap = square(30, 5, 25);
+        let mut contour = Contour::new((1e-3, 1e-3), 3e-3, 1e-3, 2e-3);
+
+        let path = contour.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::Zmove(_))).count(), 5);
+
+        contour.tabs = Some(Tabs{placement:TabPlacement::Count(4), width:2e-3, height:1.5e-3, min_radius:5e-3});
+        let path = contour.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+
+        // four tabs on the two passes below the top of the tabs
+        let top = -1.5e-3;
+        let rises : Vec<usize> = path.path.iter().enumerate()
+            .filter(|(_, m)| matches!(m, Move::Zmove(z) if f64::abs(z - top) < 1e-12))
+            .map(|(i, _)| i).collect();
+        assert_eq!(rises.len(), 8);
+
+        // the tabs are on the straight sides, away from the cut corners
+        for i in rises {
+            let (a, b) = match (path.path[i-1], path.path[i+1]) {
+                (Move::XYmove(x0, y0), Move::XYmove(x1, y1)) => ((x0, y0), (x1, y1)),
+                _ => panic!("a tab must be between two horizontal moves")
+            };
+            assert!(f64::abs(a.0 - b.0) < 1e-12 || f64::abs(a.1 - b.1) < 1e-12);
+            assert!(f64::abs(Vec2::new(a.0, a.1).distance(Vec2::new(b.0, b.1)) - 2e-3) < 1e-9);
+        }
+
+        // a tab placed by position is on the nearest side
+        contour.tabs = Some(Tabs{placement:TabPlacement::Positions(vec![(15e-3, 0.0)]), width:2e-3, height:1.5e-3, min_radius:0.0});
+        let path = contour.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        let i = path.path.iter().position(|m| matches!(m, Move::Zmove(z) if f64::abs(z - top) < 1e-12)).unwrap();
+        assert!(matches!(path.path[i-1], Move::XYmove(x, y) if f64::abs(x - 15e-3) < 1.1e-3 && f64::abs(y - 4.5e-3) < 1e-9));
+    }
+}
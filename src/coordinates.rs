@@ -0,0 +1,177 @@
+use crate::bit_map::Move;
+use crate::segment::Vec2;
+
+/// the point of the image at the origin of the paths
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Origin {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center
+}
+
+/// the height of the zero of the z-axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ZReference {
+    /// the zero is the top surface of the stock
+    Surface,
+    /// the zero is the bed of the machine, under a stock of the given thickness in `m`
+    Bed(f64)
+}
+
+/// the placement of the paths on the machine, independent of the size of the image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    pub origin : Origin,
+
+    /// if true, the x-axis of the paths points to the left of the image
+    pub flip_x : bool,
+
+    /// if true, the y-axis of the paths points to the top of the image (the image y-axis points down)
+    pub flip_y : bool,
+
+    pub z_reference : ZReference,
+
+    /// work coordinate system selected in the program, `1` for `G54` up to `6` for `G59`
+    pub work_offset : Option<usize>
+}
+
+impl Placement {
+    /// the coordinates of the image: origin at the top-left corner, y-axis pointing down
+    /// and zero at the top surface of the stock
+    pub fn new() -> Self {
+        Placement{origin:Origin::TopLeft, flip_x:false, flip_y:false, z_reference:ZReference::Surface, work_offset:None}
+    }
+
+    /// return the word selecting the work coordinate system, like `G54`
+    pub fn work_offset_word(&self) -> Option<String> {
+        self.work_offset.map(|i| format!("G{}", 53 + i))
+    }
+
+    /// return the height in the paths of a height given from the top surface of the stock
+    pub fn to_path_z(&self, z:f64) -> f64 {
+        match self.z_reference {
+            ZReference::Surface => z,
+            ZReference::Bed(thickness) => z + thickness
+        }
+    }
+
+    /// return the height from the top surface of the stock of a height of the paths
+    pub fn to_surface_z(&self, z:f64) -> f64 {
+        match self.z_reference {
+            ZReference::Surface => z,
+            ZReference::Bed(thickness) => z - thickness
+        }
+    }
+}
+
+impl Default for Placement {
+    fn default() -> Self {Self::new()}
+}
+
+/// the mapping between the pixels of a map and the coordinates in `m` of the paths,
+/// the centre of the pixel `(i, j)` is at `(i * pixel_width, j * pixel_height)` from the
+/// centre of the top-left pixel before the placement, the origins are on the centres
+/// of the pixels of the corners
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoordinateMap {
+    pub placement : Placement,
+
+    /// size in `m` of a pixel along the x-axis and the y-axis
+    pub pixel_size : (f64, f64),
+
+    /// size in pixels of the map
+    pub size : (usize, usize)
+}
+
+impl CoordinateMap {
+    pub fn new(placement:Placement, pixel_size:(f64, f64), size:(usize, usize)) -> Self {
+        CoordinateMap{placement, pixel_size, size}
+    }
+
+    /// return the position of the origin in the image, in `m` from its top-left pixel
+    fn origin(&self) -> (f64, f64) {
+        let (w, h) = (
+            self.size.0.saturating_sub(1) as f64 * self.pixel_size.0,
+            self.size.1.saturating_sub(1) as f64 * self.pixel_size.1
+        );
+        match self.placement.origin {
+            Origin::TopLeft => (0.0, 0.0),
+            Origin::TopRight => (w, 0.0),
+            Origin::BottomLeft => (0.0, h),
+            Origin::BottomRight => (w, h),
+            Origin::Center => (w / 2.0, h / 2.0)
+        }
+    }
+
+    fn signs(&self) -> (f64, f64) {
+        (if self.placement.flip_x {-1.0} else {1.0}, if self.placement.flip_y {-1.0} else {1.0})
+    }
+
+    /// return the coordinates in `m` of a point given in pixels
+    pub fn to_path(&self, p:Vec2) -> Vec2 {
+        let (ox, oy) = self.origin();
+        let (sx, sy) = self.signs();
+        Vec2::new(
+            sx * (p.get_x() * self.pixel_size.0 - ox),
+            sy * (p.get_y() * self.pixel_size.1 - oy)
+        )
+    }
+
+    /// return the coordinates in pixels of a point given in `m`
+    pub fn to_pixel(&self, p:Vec2) -> Vec2 {
+        let (ox, oy) = self.origin();
+        let (sx, sy) = self.signs();
+        Vec2::new(
+            (sx * p.get_x() + ox) / self.pixel_size.0,
+            (sy * p.get_y() + oy) / self.pixel_size.1
+        )
+    }
+
+    /// map the heights of moves given from the top surface of the stock
+    pub fn map_heights(&self, moves:Vec<Move>) -> Vec<Move> {
+        moves.into_iter().map(|m| match m {
+            Move::Zmove(z) => Move::Zmove(self.placement.to_path_z(z)),
+            Move::XYZmove(x, y, z) => Move::XYZmove(x, y, self.placement.to_path_z(z)),
+            m => m
+        }).collect()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coordinates::*;
+
+    #[test]
+    fn test_mapping() {
+        let placement = Placement{origin:Origin::BottomLeft, flip_y:true, ..Placement::new()};
+        let map = CoordinateMap::new(placement, (1e-3, 2e-3), (100, 50));
+
+        // the bottom-left pixel of the image is the origin, the y-axis points up
+        assert_eq!(map.to_path(Vec2::new(0.0, 49.0)), Vec2::new(0.0, 0.0));
+        assert_eq!(map.to_path(Vec2::new(10.0, 0.0)), Vec2::new(0.01, 0.098));
+
+        let placement = Placement{origin:Origin::Center, flip_x:true, z_reference:ZReference::Bed(0.02), work_offset:Some(2), ..Placement::new()};
+        let map = CoordinateMap::new(placement, (1e-3, 1e-3), (100, 50));
+        let p = Vec2::new(12.0, 34.0);
+        let q = map.to_pixel(map.to_path(p));
+        assert!(f64::abs(q.get_x() - p.get_x()) < 1e-9 && f64::abs(q.get_y() - p.get_y()) < 1e-9);
+        assert_eq!(map.to_path(Vec2::new(49.5, 24.5)), Vec2::new(0.0, 0.0));
+
+        // the centre is symmetric between the pixels of opposite corners
+        let (a, b) = (map.to_path(Vec2::new(0.0, 0.0)), map.to_path(Vec2::new(99.0, 49.0)));
+        assert!(f64::abs(a.get_x() + b.get_x()) < 1e-12 && f64::abs(a.get_y() + b.get_y()) < 1e-12);
+
+        // the bottom-right pixel is the origin and maps back to itself
+        let map = CoordinateMap::new(Placement{origin:Origin::BottomRight, ..Placement::new()}, (1e-3, 2e-3), (100, 50));
+        assert_eq!(map.to_path(Vec2::new(99.0, 49.0)), Vec2::new(0.0, 0.0));
+        let q = map.to_pixel(Vec2::new(0.0, 0.0));
+        assert!(f64::abs(q.get_x() - 99.0) < 1e-9 && f64::abs(q.get_y() - 49.0) < 1e-9);
+        let q = map.to_pixel(map.to_path(p));
+        assert!(f64::abs(q.get_x() - p.get_x()) < 1e-9 && f64::abs(q.get_y() - p.get_y()) < 1e-9);
+        assert_eq!(placement.to_path_z(-1e-3), 0.019);
+        assert_eq!(placement.work_offset_word(), Some("G55".to_string()));
+    }
+}
@@ -0,0 +1,278 @@
+use std::f64::consts::PI;
+
+use crate::bit_map::{BitMap, Move, Path};
+use crate::parse_config::Entry;
+use crate::segment::Vec2;
+
+/// height of the top of the stock, the material is below
+const SURFACE : f64 = 0.0;
+
+/// number of segments of a turn of helix
+const HELIX_SEGMENTS : usize = 16;
+
+/// maximum number of back and forth of a ramp along a short cut
+const MAX_RAMP_TRIPS : usize = 16;
+
+impl BitMap {
+    /// return `true` if all the pixels in the disk of center `center` and radius `radius`,
+    /// in pixel coordinates, are in the map and `true`
+    pub fn contains_disk(&self, center:Vec2, radius:f64) -> bool {
+        let (cx, cy) = (center.get_x(), center.get_y());
+
+        for x in (cx - radius).ceil() as isize..=(cx + radius).floor() as isize {
+            for y in (cy - radius).ceil() as isize..=(cy + radius).floor() as isize {
+                let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+                if dx * dx + dy * dy > radius * radius {continue;}
+                if x < 0 || y < 0 || !self.get_default(x as usize, y as usize) {return false;}
+            }
+        }
+
+        true
+    }
+}
+
+/// return the points of a polyline from its first point to the arc length `d`, without the first point
+fn walk(polyline:&[Vec2], d:f64) -> Vec<Vec2> {
+    let mut out = vec![];
+    let mut s = 0.0;
+
+    for w in polyline.windows(2) {
+        let l = w[0].distance(w[1]);
+        if s + l >= d {
+            out.push(w[0] + (w[1] - w[0]) * ((d - s) / l));
+            return out;
+        }
+        s += l;
+        out.push(w[1]);
+    }
+
+    out
+}
+
+/// push the moves of a descent through `points` (without the start point) from the height
+/// `start` to the height `target` at a constant slope, the last point is reached at `target`
+fn push_descent(from:Vec2, points:&[Vec2], start:f64, target:f64, out:&mut Vec<Move>) {
+    let total : f64 = points.iter().scan(from, |p, q| {
+        let d = p.distance(*q);
+        *p = *q;
+        Some(d)
+    }).sum();
+
+    let mut travelled = 0.0;
+    let mut position = from;
+    for (i, p) in points.iter().enumerate() {
+        travelled += position.distance(*p);
+        position = *p;
+        let z = if i + 1 == points.len() {target} else {start + (target - start) * travelled / total};
+        out.push(Move::XYZmove(p.get_x(), p.get_y(), z));
+    }
+}
+
+impl Path {
+    /// replace the plunges in the material (the `Zmove` going down below `0.0`) by a helix
+    /// if `fits(center, radius)` tells that a circle of tool positions fits in the region cut,
+    /// or else by a ramp going back and forth along the next cutting moves,
+    /// the descent is never steeper than `entry.max_angle`, and the plunge is kept
+    /// if neither the helix nor the ramp fits
+    pub fn with_entries<F>(&self, entry:&Entry, fits:F) -> Path
+        where
+            F: Fn(Vec2, f64) -> bool
+    {
+        let mut out = vec![];
+        let mut position = Vec2::new(self.x_init, self.y_init);
+        let mut z = self.z_init;
+
+        for (i, m) in self.path.iter().enumerate() {
+            match *m {
+                Move::Zmove(target) if target < z && target < SURFACE => {
+                    let start = f64::min(z, SURFACE);
+
+                    // horizontal length of the descent
+                    let length = (start - target) / entry.max_angle.tan();
+
+                    let next : Vec<Vec2> = self.path[i+1..].iter()
+                        .map_while(|m| if let Move::XYmove(x, y) = m {Some(Vec2::new(*x, *y))} else {None})
+                        .collect();
+
+                    // the tool goes down to the surface before the helix or the ramp
+                    let mut descent = if z > start {vec![Move::Zmove(start)]} else {vec![]};
+                    if Self::push_helix(entry, &fits, position, start, target, length, &mut descent) ||
+                       Self::push_ramp(position, &next, start, target, length, &mut descent) {
+                        out.extend(descent);
+                    } else {
+                        out.push(*m);
+                    }
+                    z = target;
+                },
+                Move::XYmove(x, y) | Move::FXYmove(x, y) => {
+                    position = Vec2::new(x, y);
+                    out.push(*m);
+                },
+                Move::XYZmove(x, y, new_z) => {
+                    position = Vec2::new(x, y);
+                    z = new_z;
+                    out.push(*m);
+                },
+                Move::Zmove(new_z) => {
+                    z = new_z;
+                    out.push(*m);
+                },
+                Move::Feed(_) => out.push(*m)
+            }
+        }
+
+        Path{x_init:self.x_init, y_init:self.y_init, z_init:self.z_init, path:out}
+    }
+
+    /// push a helix through `p` descending from `start` to `target`, followed by a flat turn,
+    /// return `false` if it doesn't fit
+    fn push_helix<F>(entry:&Entry, fits:&F, p:Vec2, start:f64, target:f64, length:f64, out:&mut Vec<Move>) -> bool
+        where
+            F: Fn(Vec2, f64) -> bool
+    {
+        let r = entry.helix_radius;
+        if r <= 0.0 {return false;}
+
+        let center = [Vec2::new(r, 0.0), Vec2::new(-r, 0.0), Vec2::new(0.0, r), Vec2::new(0.0, -r)]
+            .into_iter().map(|offset| p + offset).find(|c| fits(*c, r));
+        let center = match center {
+            Some(center) => center,
+            None => return false
+        };
+
+        // the helix is cut along the chords of its arcs, a turn is shorter than the circle
+        let chords = HELIX_SEGMENTS as f64 * 2.0 * r * f64::sin(PI / HELIX_SEGMENTS as f64);
+        let turns = usize::max(1, (length / chords - 1e-9).ceil() as usize);
+        let theta = f64::atan2(p.get_y() - center.get_y(), p.get_x() - center.get_x());
+        let point = |k:usize| {
+            let a = theta + 2.0 * PI * k as f64 / HELIX_SEGMENTS as f64;
+            center + Vec2::new(a.cos(), a.sin()) * r
+        };
+
+        let n = turns * HELIX_SEGMENTS;
+        for k in 1..=n {
+            let q = if k % HELIX_SEGMENTS == 0 {p} else {point(k)};
+            let z = if k == n {target} else {start + (target - start) * k as f64 / n as f64};
+            out.push(Move::XYZmove(q.get_x(), q.get_y(), z));
+        }
+        for k in 1..=HELIX_SEGMENTS {
+            let q = if k == HELIX_SEGMENTS {p} else {point(k)};
+            out.push(Move::XYmove(q.get_x(), q.get_y()));
+        }
+
+        true
+    }
+
+    /// push a ramp going back and forth from `p` along the polyline `next`, descending
+    /// from `start` to `target`, return `false` if `next` is too short
+    fn push_ramp(p:Vec2, next:&[Vec2], start:f64, target:f64, length:f64, out:&mut Vec<Move>) -> bool {
+        let mut polyline = vec![p];
+        polyline.extend(next.iter().copied());
+        let available : f64 = polyline.windows(2).map(|w| w[0].distance(w[1])).sum();
+
+        if available <= 0.0 {return false;}
+        let trips = usize::max(1, (length / (2.0 * available) - 1e-9).ceil() as usize);
+        if trips > MAX_RAMP_TRIPS {return false;}
+
+        let forward = walk(&polyline, length / (2.0 * trips as f64));
+        let mut trip = forward.clone();
+        trip.extend(forward.iter().rev().skip(1).copied());
+        trip.push(p);
+
+        let mut points = vec![];
+        for _ in 0..trips {points.extend(trip.iter().copied());}
+        push_descent(p, &points, start, target, out);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entry::*;
+
+    /// return the steepest slope of the moves of a path
+    fn max_slope(path:&Path) -> f64 {
+        let (mut position, mut z) = (Vec2::new(path.x_init, path.y_init), path.z_init);
+        let mut slope : f64 = 0.0;
+        for m in path.path.iter() {
+            if let Move::XYZmove(x, y, new_z) = m {
+                let d = position.distance(Vec2::new(*x, *y));
+                slope = slope.max(f64::abs(new_z - z) / d);
+            }
+            match m {
+                Move::XYmove(x, y) | Move::FXYmove(x, y) => position = Vec2::new(*x, *y),
+                Move::XYZmove(x, y, new_z) => {position = Vec2::new(*x, *y); z = *new_z;},
+                Move::Zmove(new_z) => z = *new_z,
+                Move::Feed(_) => {}
+            }
+        }
+        slope
+    }
+
+    fn line(length:f64) -> Path {
+        Path{x_init:0.0, y_init:0.0, z_init:0.0, path:vec![
+            Move::Zmove(1.0),
+            Move::FXYmove(0.0, 0.0),
+            Move::Zmove(-1.0),
+            Move::XYmove(length, 0.0),
+            Move::Zmove(1.0)
+        ]}
+    }
+
+    #[test]
+    fn test_ramp() {
+        let entry = Entry{max_angle:f64::atan(0.1), helix_radius:0.0};
+
+        let path = line(10.0).with_entries(&entry, |_, _| true);
+        assert_eq!(path.path, vec![
+            Move::Zmove(1.0),
+            Move::FXYmove(0.0, 0.0),
+            Move::Zmove(0.0),
+            Move::XYZmove(5.0, 0.0, -0.5),
+            Move::XYZmove(0.0, 0.0, -1.0),
+            Move::XYmove(10.0, 0.0),
+            Move::Zmove(1.0)
+        ]);
+
+        // several back and forth along a short cut
+        let path = line(1.0).with_entries(&entry, |_, _| true);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::XYZmove(_, _, _))).count(), 10);
+        assert!(max_slope(&path) <= 0.1 + 1e-9);
+
+        // no room for a ramp
+        let path = line(0.01).with_entries(&entry, |_, _| true);
+        assert_eq!(path.path, line(0.01).path);
+    }
+
+    #[test]
+    fn test_helix() {
+        let entry = Entry{max_angle:f64::atan(0.1), helix_radius:1.0};
+
+        let path = line(10.0).with_entries(&entry, |_, _| true);
+        let helix : Vec<&Move> = path.path.iter().filter(|m| matches!(m, Move::XYZmove(_, _, _))).collect();
+        assert_eq!(helix.len(), 2 * HELIX_SEGMENTS);
+        assert!(matches!(helix[helix.len()-1], Move::XYZmove(x, y, z) if *x == 0.0 && *y == 0.0 && *z == -1.0));
+        assert!(max_slope(&path) <= 0.1 + 1e-9);
+
+        // two turns of the circle are long enough, but not two turns of the chords
+        let entry = Entry{max_angle:f64::atan(0.1), helix_radius:0.8};
+        let path = line(10.0).with_entries(&entry, |_, _| true);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::XYZmove(_, _, _))).count(), 3 * HELIX_SEGMENTS);
+        assert!(max_slope(&path) <= 0.1 + 1e-9);
+        let entry = Entry{max_angle:f64::atan(0.1), helix_radius:1.0};
+
+        // the helix doesn't fit, the tool ramps along the cut
+        let path = line(10.0).with_entries(&entry, |_, _| false);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::XYZmove(_, _, _))).count(), 2);
+
+        let mut bmap = BitMap::new(10, 10);
+        for x in 2..9 {
+            for y in 2..9 {
+                bmap.set(x, y, true);
+            }
+        }
+        assert!(bmap.contains_disk(Vec2::new(5.0, 5.0), 3.0));
+        assert!(!bmap.contains_disk(Vec2::new(5.0, 5.0), 4.0));
+    }
+}
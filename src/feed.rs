@@ -0,0 +1,284 @@
+use crate::bit_map::{Move, Path};
+use crate::height_map::HeightMap;
+use crate::parse_config::{AdaptiveFeed, ToolShape};
+
+/// number of steps between the minimum and the maximum speed, the adapted speeds are
+/// rounded down to one of these steps to avoid a new `F` word on every move
+const FEED_STEPS : usize = 16;
+
+/// maximum number of cells of the simulated stock, the cells are enlarged above
+const MAX_STOCK_CELLS : usize = 1 << 24;
+
+/// the material removed by a working move, where it is the heaviest along the move
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Engagement {
+    /// width in `m` of the material removed across the move (radial engagement)
+    pub width : f64,
+
+    /// depth in `m` of the material removed (axial engagement)
+    pub depth : f64,
+
+    /// area in `m^2` of the section of the material removed, the volume removed
+    /// by unit of length of the move
+    pub section : f64
+}
+
+/// return the height of the surface of the tool above its tip at the distance `d`
+/// from its axis, `None` outside of the tool
+fn tool_height(tool:ToolShape, d:f64) -> Option<f64> {
+    let r = tool.get_rayon();
+    if d > r {return None;}
+    Some(match tool {
+        ToolShape::Flat(_) => 0.0,
+        ToolShape::Ball(r) => r - f64::sqrt(r * r - d * d),
+        ToolShape::V(_, theta) => d / f64::tan(theta / 2.0)
+    })
+}
+
+/// the stock simulated by the height of its top on a grid of square cells
+struct Stock {
+    heights : HeightMap,
+    origin : (f64, f64),
+    cell : f64,
+    tool : ToolShape
+}
+
+impl Stock {
+    /// a flat stock at the height `surface` covering all the positions of the tool along `path`,
+    /// its cells are of size `cell`, or larger such that there are at most about `MAX_STOCK_CELLS`
+    fn new(path:&Path, tool:ToolShape, surface:f64, cell:f64) -> Self {
+        let (mut min, mut max) = ((path.x_init, path.y_init), (path.x_init, path.y_init));
+        for m in path.path.iter() {
+            if let Move::XYmove(x, y) | Move::FXYmove(x, y) | Move::XYZmove(x, y, _) = *m {
+                min = (f64::min(min.0, x), f64::min(min.1, y));
+                max = (f64::max(max.0, x), f64::max(max.1, y));
+            }
+        }
+
+        let area = (max.0 - min.0 + 2.0 * tool.get_rayon()) * (max.1 - min.1 + 2.0 * tool.get_rayon());
+        let cell = f64::max(cell, f64::sqrt(area / MAX_STOCK_CELLS as f64));
+
+        let margin = tool.get_rayon() + cell;
+        let origin = (min.0 - margin, min.1 - margin);
+        let width = ((max.0 - min.0 + 2.0 * margin) / cell).ceil() as usize + 1;
+        let height = ((max.1 - min.1 + 2.0 * margin) / cell).ceil() as usize + 1;
+
+        let heights = HeightMap::new_with_buffer(width, height, vec![surface; width * height]);
+        Stock{heights, origin, cell, tool}
+    }
+
+    /// remove the material under the tool with its tip at `(x, y, z)`, return the volume
+    /// removed, the area of the cells lowered and the largest height removed
+    fn cut(&mut self, x:f64, y:f64, z:f64) -> (f64, f64, f64) {
+        let r = self.tool.get_rayon();
+        let to_cell = |v:f64, o:f64| ((v - o) / self.cell).round() as isize;
+        let (i0, i1) = (to_cell(x - r, self.origin.0), to_cell(x + r, self.origin.0));
+        let (j0, j1) = (to_cell(y - r, self.origin.1), to_cell(y + r, self.origin.1));
+
+        let (mut volume, mut area, mut depth) = (0.0, 0.0, 0.0);
+
+        for i in isize::max(0, i0)..=isize::min(i1, self.heights.get_width() as isize - 1) {
+            for j in isize::max(0, j0)..=isize::min(j1, self.heights.get_height() as isize - 1) {
+                let (cx, cy) = (self.origin.0 + i as f64 * self.cell, self.origin.1 + j as f64 * self.cell);
+                let (dx, dy) = (cx - x, cy - y);
+                let bottom = match tool_height(self.tool, f64::sqrt(dx * dx + dy * dy)) {
+                    Some(h) => z + h,
+                    None => continue
+                };
+
+                let top = self.heights.get(i as usize, j as usize);
+                if top > bottom {
+                    volume += (top - bottom) * self.cell * self.cell;
+                    area += self.cell * self.cell;
+                    depth = f64::max(depth, top - bottom);
+                    self.heights.set(i as usize, j as usize, bottom);
+                }
+            }
+        }
+
+        (volume, area, depth)
+    }
+}
+
+impl Path {
+    /// return the engagement of the tool `tool` during each working move (`XYmove` and
+    /// `XYZmove`) of the path, `None` for the other moves, in a stock whose top is at the
+    /// height `surface` simulated on cells of size `resolution` in `m` (larger if the path
+    /// covers too many of them)
+    pub fn engagements(&self, tool:ToolShape, surface:f64, resolution:f64) -> Vec<Option<Engagement>> {
+        let mut stock = Stock::new(self, tool, surface, resolution);
+        let resolution = stock.cell;
+        let (mut x, mut y, mut z) = (self.x_init, self.y_init, self.z_init);
+
+        self.path.iter().map(|m| match *m {
+            Move::XYmove(nx, ny) | Move::XYZmove(nx, ny, _) => {
+                let nz = if let Move::XYZmove(_, _, nz) = *m {nz} else {z};
+                let (dx, dy, dz) = (nx - x, ny - y, nz - z);
+                let horizontal = f64::sqrt(dx * dx + dy * dy);
+                let length = f64::sqrt(horizontal * horizontal + dz * dz);
+
+                let n = usize::max(1, (length / resolution).ceil() as usize);
+                let steps : Vec<(f64, f64, f64)> = (1..=n).map(|k| {
+                    let t = k as f64 / n as f64;
+                    stock.cut(x + dx * t, y + dy * t, z + dz * t)
+                }).collect();
+
+                // the heaviest part of the move as long as the rayon of the tool, the steps
+                // alone depend too much on the cells entering the tool, the width is the
+                // area uncovered by unit of length
+                let window = usize::min(n, usize::max(1, (tool.get_rayon() / resolution).ceil() as usize));
+                let mut heaviest = Engagement{width:0.0, depth:0.0, section:0.0};
+                for part in steps.windows(window) {
+                    let (volume, area) = part.iter().fold((0.0, 0.0), |(v, a), s| (v + s.0, a + s.1));
+                    let section = if length > 0.0 {volume * n as f64 / (window as f64 * length)} else {0.0};
+                    if section >= heaviest.section {
+                        let width = if horizontal > 0.0 {area * n as f64 / (window as f64 * horizontal)}
+                            else if area > 0.0 {2.0 * tool.get_rayon()} else {0.0};
+                        heaviest = Engagement{width, depth:part.iter().map(|s| s.2).fold(0.0, f64::max), section};
+                    }
+                }
+
+                (x, y, z) = (nx, ny, nz);
+                Some(heaviest)
+            },
+            Move::FXYmove(nx, ny) => {
+                (x, y) = (nx, ny);
+                None
+            },
+            Move::Zmove(nz) => {
+                if nz < z {stock.cut(x, y, nz);}
+                z = nz;
+                None
+            },
+            Move::Feed(_) => None
+        }).collect()
+    }
+
+    /// return the path with a `Feed` before the working moves whose speed changes, the speed
+    /// keeps the volume removed by unit of time of a slot of depth `feed.reference_depth` cut
+    /// at `work_speed`, in the range of `feed`, and it is multiplied by `cos(angle / 2)` after
+    /// a change of direction of `angle` between two working moves, the first working move
+    /// after each rapid move sets its speed such that the sub-paths can be reordered
+    pub fn with_adaptive_feed(&self, feed:&AdaptiveFeed, tool:ToolShape, work_speed:f64, surface:f64) -> Path {
+        let engagements = self.engagements(tool, surface, feed.resolution);
+        let reference = 2.0 * tool.get_rayon() * feed.reference_depth;
+        let step = (feed.max_speed - feed.min_speed) / FEED_STEPS as f64;
+
+        let mut out = vec![];
+        let mut current : Option<f64> = None;
+        let mut position = (self.x_init, self.y_init);
+        // horizontal direction of the previous working move
+        let mut direction : Option<(f64, f64)> = None;
+        for (m, engagement) in self.path.iter().zip(engagements) {
+            let previous = position;
+            if let Move::XYmove(x, y) | Move::FXYmove(x, y) | Move::XYZmove(x, y, _) = *m {position = (x, y);}
+
+            match (m, engagement) {
+                (Move::FXYmove(_, _), _) | (Move::Feed(_), _) => {
+                    current = None;
+                    direction = None;
+                },
+                (_, Some(engagement)) => {
+                    let speed = if engagement.section > 0.0 {work_speed * reference / engagement.section} else {feed.max_speed};
+
+                    // slow down in the corners, cos(angle / 2) = sqrt((1 + cos(angle)) / 2)
+                    let (dx, dy) = (position.0 - previous.0, position.1 - previous.1);
+                    let length = f64::sqrt(dx * dx + dy * dy);
+                    let speed = if length > 0.0 {
+                        let corner = direction.map(|(ux, uy)| f64::sqrt((1.0 + (ux * dx + uy * dy) / length).max(0.0) / 2.0));
+                        direction = Some((dx / length, dy / length));
+                        speed * corner.unwrap_or(1.0)
+                    } else {speed};
+
+                    let speed = if step > 0.0 {
+                        let k = ((speed - feed.min_speed) / step).floor().clamp(0.0, FEED_STEPS as f64);
+                        feed.min_speed + k * step
+                    } else {feed.min_speed};
+
+                    if current != Some(speed) {
+                        out.push(Move::Feed(speed));
+                        current = Some(speed);
+                    }
+                },
+                (Move::Zmove(_), _) => direction = None,
+                _ => {}
+            }
+            if !matches!(m, Move::Feed(_)) {out.push(*m);}
+        }
+
+        Path{x_init:self.x_init, y_init:self.y_init, z_init:self.z_init, path:out}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::feed::*;
+
+    #[test]
+    fn test_engagement() {
+        let tool = ToolShape::Flat(1e-3);
+
+        // a slot, then a pass back overlapping it by half its width
+        let path = Path{x_init:0.0, y_init:0.0, z_init:1e-3, path:vec![
+            Move::Zmove(-1e-3),
+            Move::XYmove(1e-2, 0.0),
+            Move::XYmove(1e-2, 1e-3),
+            Move::XYmove(0.0, 1e-3),
+            Move::Zmove(1e-3),
+            Move::FXYmove(0.0, 5e-3),
+            Move::XYmove(1e-2, 5e-3)
+        ]};
+        let engagements = path.engagements(tool, 0.0, 1e-4);
+        assert_eq!(engagements[0], None);
+
+        let slot = engagements[1].unwrap();
+        assert!(f64::abs(slot.width - 2e-3) < 2e-4 && f64::abs(slot.depth - 1e-3) < 1e-12);
+        assert!(f64::abs(slot.section - 2e-6) < 2e-7);
+
+        let half = engagements[3].unwrap();
+        assert!(f64::abs(half.width - 1e-3) < 2.5e-4 && half.section < 0.65 * slot.section);
+        assert_eq!(engagements[6].unwrap().section, 0.0);
+
+        // the slot at the reference speed, slower after the corner of the stepover, faster
+        // in the half-width pass despite its corner, and at the max speed in the air
+        let feed = AdaptiveFeed{reference_depth:1e-3, min_speed:5e-4, max_speed:4e-3, resolution:1e-4};
+        let adapted = path.with_adaptive_feed(&feed, tool, 1e-3, 0.0);
+        let speeds : Vec<f64> = adapted.path.iter().filter_map(|m| if let Move::Feed(s) = m {Some(*s)} else {None}).collect();
+        assert_eq!(speeds.len(), 4);
+        assert!(speeds[0] <= 1e-3 && speeds[0] > 0.8e-3);
+        assert!(speeds[1] < speeds[0]);
+        assert!(speeds[2] > 1.2 * speeds[0]);
+        assert_eq!(speeds[3], 4e-3);
+        assert_eq!(adapted.path.iter().filter(|m| !matches!(m, Move::Feed(_))).count(), path.path.len());
+    }
+
+    #[test]
+    fn test_corners() {
+        let tool = ToolShape::Flat(1e-3);
+        let feed = AdaptiveFeed{reference_depth:1e-3, min_speed:5e-4, max_speed:4e-3, resolution:1e-4};
+
+        // in the air, the speed is reduced after the right angle and the half-turn only
+        let path = Path{x_init:0.0, y_init:0.0, z_init:1e-3, path:vec![
+            Move::XYmove(1e-2, 0.0),
+            Move::XYmove(2e-2, 0.0),
+            Move::XYmove(2e-2, 1e-2),
+            Move::XYmove(2e-2, 0.0)
+        ]};
+        let adapted = path.with_adaptive_feed(&feed, tool, 1e-3, 0.0);
+        assert_eq!(adapted.path, vec![
+            Move::Feed(4e-3),
+            Move::XYmove(1e-2, 0.0),
+            Move::XYmove(2e-2, 0.0),
+            Move::Feed(5e-4 + 10.0 * 3.5e-3 / 16.0),
+            Move::XYmove(2e-2, 1e-2),
+            Move::Feed(5e-4),
+            Move::XYmove(2e-2, 0.0)
+        ]);
+
+        // the cells of the stock of a long path are enlarged
+        let long = Path{x_init:0.0, y_init:0.0, z_init:0.0, path:vec![Move::XYmove(1.0, 1.0)]};
+        let stock = Stock::new(&long, tool, 0.0, 1e-4);
+        assert!(stock.heights.get_width() * stock.heights.get_height() <= MAX_STOCK_CELLS + MAX_STOCK_CELLS / 100);
+        assert!(stock.cell > 1e-4);
+    }
+}
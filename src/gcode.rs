@@ -0,0 +1,458 @@
+use crate::bit_map::{Move, Path};
+use crate::parse_config::Config;
+use crate::segment::Vec2;
+
+/// the controllers supported by the post-processor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dialect {
+    Grbl,
+    LinuxCnc,
+    Marlin,
+    Mach3
+}
+
+/// the way the comments are written in the program
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommentStyle {
+    /// `(comment)`
+    Parentheses,
+    /// `; comment`
+    Semicolon
+}
+
+/// the settings of the translation of a `Path` into G-code for a controller,
+/// `PostProcessor::new` gives the settings of a dialect, each one can be changed after
+#[derive(Clone, Debug, PartialEq)]
+pub struct PostProcessor {
+    pub dialect : Dialect,
+
+    /// number of decimals of the coordinates in `mm`
+    pub precision : usize,
+
+    /// if true, each line starts with a line number `N<n>`
+    pub line_numbers : bool,
+
+    /// if true, each numbered line ends with the checksum `*<xor of the bytes>`
+    pub checksums : bool,
+
+    /// if true, the arcs fitted on the moves are written with `G2` and `G3`
+    pub arcs : bool,
+
+    /// if true, the controller accepts the path blending `G64 P<tolerance>`
+    pub path_blending : bool,
+
+    pub comments : CommentStyle,
+
+    /// lines written before the moves, with the placeholders
+    /// `{fly_z}` (in `mm`), `{tolerance}` (in `mm`) and `{spindle}` (`M3` with the spindle speed)
+    pub header : String,
+
+    /// lines written after the moves, with the same placeholders as `header`
+    pub footer : String,
+
+    /// maximum distance in `m` between the moves and the arcs fitted on them
+    pub tolerance : f64
+}
+
+impl PostProcessor {
+    pub fn new(dialect:Dialect) -> Self {
+        match dialect {
+            Dialect::Grbl => PostProcessor{
+                dialect,
+                precision:3,
+                line_numbers:false,
+                checksums:false,
+                arcs:true,
+                path_blending:false,
+                comments:CommentStyle::Parentheses,
+                header:"G21 G90 G17 G94\n{spindle}\nG0 Z{fly_z}".to_string(),
+                footer:"G0 Z{fly_z}\nM5\nM30".to_string(),
+                tolerance:1e-5
+            },
+            Dialect::LinuxCnc => PostProcessor{
+                dialect,
+                precision:4,
+                line_numbers:false,
+                checksums:false,
+                arcs:true,
+                path_blending:true,
+                comments:CommentStyle::Parentheses,
+                header:"%\nG21 G90 G17 G94 G40 G49\nG64 P{tolerance}\n{spindle}\nG0 Z{fly_z}".to_string(),
+                footer:"G0 Z{fly_z}\nM5\nM2\n%".to_string(),
+                tolerance:1e-5
+            },
+            Dialect::Marlin => PostProcessor{
+                dialect,
+                precision:3,
+                line_numbers:true,
+                checksums:true,
+                arcs:true,
+                path_blending:false,
+                comments:CommentStyle::Semicolon,
+                header:"M110\nG21\nG90\n{spindle}\nG0 Z{fly_z}".to_string(),
+                footer:"G0 Z{fly_z}\nM5\nM84".to_string(),
+                tolerance:1e-5
+            },
+            Dialect::Mach3 => PostProcessor{
+                dialect,
+                precision:4,
+                line_numbers:false,
+                checksums:false,
+                arcs:true,
+                path_blending:true,
+                comments:CommentStyle::Parentheses,
+                header:"%\nG21 G90 G17 G40 G49 G80\nG64 P{tolerance}\n{spindle}\nG0 Z{fly_z}".to_string(),
+                footer:"G0 Z{fly_z}\nM5\nM30\n%".to_string(),
+                tolerance:1e-5
+            }
+        }
+    }
+
+    /// return the dialect of its name in the configuration
+    pub fn dialect_from_name(name:&str) -> Option<Dialect> {
+        match name {
+            "grbl" => Some(Dialect::Grbl),
+            "linuxcnc" => Some(Dialect::LinuxCnc),
+            "marlin" => Some(Dialect::Marlin),
+            "mach3" => Some(Dialect::Mach3),
+            _ => None
+        }
+    }
+
+    /// write a length in `m` as `mm` with the precision of the dialect
+    fn number(&self, value:f64) -> String {
+        let out = format!("{:.*}", self.precision, value * 1e3);
+        // avoid `-0.000`
+        if out.trim_start_matches('-').chars().all(|c| c == '0' || c == '.') {
+            out.trim_start_matches('-').to_string()
+        } else {out}
+    }
+
+    /// write a speed in `m / s` as `mm / min`
+    fn feed(&self, speed:f64) -> String {
+        format!("{:.1}", speed * 6e4)
+    }
+
+    /// return a comment in the style of the dialect
+    pub fn comment(&self, text:&str) -> String {
+        match self.comments {
+            CommentStyle::Parentheses => format!("({})", text.replace(['(', ')'], "")),
+            CommentStyle::Semicolon => format!("; {}", text)
+        }
+    }
+
+    /// return `true` if the controller accepts the G-code word (like `G64`)
+    pub fn supports(&self, word:&str) -> bool {
+        match word {
+            "G64" => self.path_blending,
+            "G2" | "G3" => self.arcs,
+            "N" => self.line_numbers,
+            _ => true
+        }
+    }
+
+    /// replace the placeholders of a template, and remove its lines
+    /// starting with a word that the controller doesn't accept
+    fn fill(&self, template:&str, config:&Config) -> Vec<String> {
+        let spindle = match config.spindle_speed {
+            Some(speed) => format!("M3 S{:.0}", speed),
+            None => "M3".to_string()
+        };
+        template
+            .replace("{fly_z}", &self.number(config.placement.to_path_z(config.fly_z)))
+            .replace("{tolerance}", &self.number(self.tolerance))
+            .replace("{spindle}", &spindle)
+            .lines()
+            .filter(|line| line.split_whitespace().next().map(|w| self.supports(w)).unwrap_or(true))
+            .map(|line| line.to_string())
+            .collect()
+    }
+
+    /// translate a path into a program, the coordinates are converted in `mm` and the
+    /// speeds of `config` in `mm / min`, the rapid moves and the moves going up are `G0`,
+    /// the working moves go at the speed of the last `Feed` of the path if any
+    pub fn write(&self, path:&Path, config:&Config) -> String {
+        // the `%` starting a program must be its first line, the comment and the work
+        // coordinate system come after it, before the first move
+        let mut header = self.fill(&self.header, config);
+        let mut lines = if header.first().map(|l| l == "%").unwrap_or(false) {header.drain(..1).collect()} else {vec![]};
+        lines.push(self.comment(&format!("generated for {:?}", self.dialect)));
+        if let Some(word) = config.placement.work_offset_word() {
+            lines.push(word);
+        }
+        lines.extend(header);
+
+        let instructions = if self.arcs {fit_arcs(path, self.tolerance)}
+            else {path.path.iter().map(|m| Instruction::Move(*m)).collect()};
+
+        let mut feed : Option<String> = None;
+        let mut with_feed = |speed:f64, line:String| -> String {
+            let f = self.feed(speed);
+            if feed.as_ref() == Some(&f) {line} else {
+                feed = Some(f.clone());
+                format!("{} F{}", line, f)
+            }
+        };
+
+        let mut position = Vec2::new(path.x_init, path.y_init);
+        let mut z = path.z_init;
+        let mut work_speed = config.horizontal_work_speed;
+
+        for instruction in instructions {
+            let line = match instruction {
+                Instruction::Move(Move::FXYmove(x, y)) => {
+                    position = Vec2::new(x, y);
+                    format!("G0 X{} Y{}", self.number(x), self.number(y))
+                },
+                Instruction::Move(Move::XYmove(x, y)) => {
+                    position = Vec2::new(x, y);
+                    with_feed(work_speed, format!("G1 X{} Y{}", self.number(x), self.number(y)))
+                },
+                Instruction::Move(Move::Zmove(new_z)) => {
+                    let up = new_z >= z;
+                    z = new_z;
+                    if up {format!("G0 Z{}", self.number(new_z))}
+                    else {with_feed(config.vectical_speed, format!("G1 Z{}", self.number(new_z)))}
+                },
+                Instruction::Move(Move::XYZmove(x, y, new_z)) => {
+                    position = Vec2::new(x, y);
+                    z = new_z;
+                    with_feed(work_speed, format!(
+                        "G1 X{} Y{} Z{}", self.number(x), self.number(y), self.number(new_z)
+                    ))
+                },
+                Instruction::Arc{end, center, clockwise} => {
+                    let offset = center - position;
+                    position = end;
+                    with_feed(work_speed, format!(
+                        "{} X{} Y{} I{} J{}",
+                        if clockwise {"G2"} else {"G3"},
+                        self.number(end.get_x()), self.number(end.get_y()),
+                        self.number(offset.get_x()), self.number(offset.get_y())
+                    ))
+                },
+                // the F word is written with the next working move
+                Instruction::Move(Move::Feed(speed)) => {
+                    work_speed = speed;
+                    continue;
+                }
+            };
+            lines.push(line);
+        }
+
+        lines.extend(self.fill(&self.footer, config));
+
+        self.number_lines(lines)
+    }
+
+    /// add the line numbers and the checksums, the comment lines are not numbered
+    fn number_lines(&self, lines:Vec<String>) -> String {
+        let mut out = String::new();
+        let mut n = 0;
+
+        for line in lines {
+            let is_comment = line.starts_with(';') || line.starts_with('(');
+            if self.line_numbers && !is_comment && line != "%" {
+                n += 1;
+                let numbered = format!("N{} {}", n, line);
+                if self.checksums {
+                    let checksum = numbered.bytes().fold(0u8, |acc, b| acc ^ b);
+                    out.push_str(&format!("{}*{}\n", numbered, checksum));
+                } else {
+                    out.push_str(&numbered);
+                    out.push('\n');
+                }
+            } else {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+
+        out
+    }
+}
+
+/// a move of a path, or an arc in the plane fitted on several `XYmove`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Instruction {
+    Move(Move),
+    Arc{end:Vec2, center:Vec2, clockwise:bool}
+}
+
+/// return the center of the circle through three points, if they are not aligned
+fn circle_center(a:Vec2, b:Vec2, c:Vec2) -> Option<Vec2> {
+    let d = 2.0 * (b - a).cross(c - a);
+    if d.abs() < 1e-18 {return None;}
+
+    let (ab, ac) = (b - a, c - a);
+    let (lb, lc) = (ab * ab, ac * ac);
+    let x = (ac.get_y() * lb - ab.get_y() * lc) / d;
+    let y = (ab.get_x() * lc - ac.get_x() * lb) / d;
+    Some(a + Vec2::new(x, y))
+}
+
+/// return the arc through the points if it is closer than `tolerance` to all the points
+/// and to the segments between them, turns in one direction and has at least 4 points
+fn arc_through(points:&[Vec2], tolerance:f64) -> Option<(Vec2, bool)> {
+    if points.len() < 4 {return None;}
+
+    let n = points.len();
+    let center = circle_center(points[0], points[n/2], points[n-1])?;
+    let r = (points[0] - center).norm();
+
+    // the nearly straight lines are better written as lines
+    let chord = points[n-1] - points[0];
+    let straight = points.iter().all(|p| ((*p - points[0]).cross(chord) / chord.norm()).abs() <= tolerance);
+    if chord.norm() == 0.0 || straight {return None;}
+
+    let clockwise = (points[1] - points[0]).cross(points[2] - points[1]) < 0.0;
+    let mut sweep = 0.0;
+
+    for w in points.windows(2) {
+        if ((w[1] - center).norm() - r).abs() > tolerance {return None;}
+
+        let turn = (w[0] - center).cross(w[1] - center);
+        if turn == 0.0 || (turn < 0.0) != clockwise {return None;}
+        sweep += f64::atan2(turn.abs(), (w[0] - center) * (w[1] - center));
+
+        // distance between the middle of the segment and the arc
+        let half = (w[1] - w[0]).norm() / 2.0;
+        if r - f64::sqrt(f64::max(0.0, r * r - half * half)) > tolerance {return None;}
+    }
+
+    if sweep >= 2.0 * std::f64::consts::PI - 1e-6 {return None;}
+    Some((center, clockwise))
+}
+
+/// replace the sequences of `XYmove` close to an arc of circle by this arc
+fn fit_arcs(path:&Path, tolerance:f64) -> Vec<Instruction> {
+    let mut out = vec![];
+    let mut position = Vec2::new(path.x_init, path.y_init);
+    let mut i = 0;
+
+    while i < path.path.len() {
+        // the points of the longest sequence of `XYmove` starting at `i`
+        let mut points = vec![position];
+        points.extend(path.path[i..].iter()
+            .map_while(|m| if let Move::XYmove(x, y) = m {Some(Vec2::new(*x, *y))} else {None}));
+
+        if points.len() == 1 {
+            if let Move::FXYmove(x, y) | Move::XYZmove(x, y, _) = path.path[i] {position = Vec2::new(x, y);}
+            out.push(Instruction::Move(path.path[i]));
+            i += 1;
+            continue;
+        }
+
+        let mut k = 0;
+        while k + 1 < points.len() {
+            // extend the arc as long as it fits
+            let mut best = None;
+            for j in k+3..points.len() {
+                match arc_through(&points[k..=j], tolerance) {
+                    Some(arc) => best = Some((j, arc)),
+                    None => break
+                }
+            }
+
+            match best {
+                Some((j, (center, clockwise))) => {
+                    out.push(Instruction::Arc{end:points[j], center, clockwise});
+                    k = j;
+                },
+                None => {
+                    out.push(Instruction::Move(Move::XYmove(points[k+1].get_x(), points[k+1].get_y())));
+                    k += 1;
+                }
+            }
+        }
+
+        position = points[points.len()-1];
+        i += points.len() - 1;
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gcode::*;
+    use crate::parse_config::test_config;
+
+    fn square() -> Path {
+        Path{x_init:0.0, y_init:0.0, z_init:0.0, path:vec![
+            Move::Zmove(2e-3),
+            Move::FXYmove(1e-2, 1e-2),
+            Move::Zmove(-1e-3),
+            Move::XYmove(2e-2, 1e-2),
+            Move::XYmove(2e-2, 2e-2),
+            Move::XYmove(1e-2, 1e-2),
+            Move::Zmove(2e-3)
+        ]}
+    }
+
+    #[test]
+    fn test_dialects() {
+        let config = test_config("{}").unwrap();
+
+        let grbl = PostProcessor::new(Dialect::Grbl).write(&square(), &config);
+        assert!(!grbl.contains("G64"));
+        assert!(grbl.contains("G0 X10.000 Y10.000\n"));
+        assert!(grbl.contains("G1 Z-1.000 F60.0\n"));
+        assert!(grbl.contains("G1 X20.000 Y10.000 F600.0\nG1 X20.000 Y20.000\n"));
+        assert!(grbl.starts_with("(generated for Grbl)\n"));
+
+        // a feed set by the path is written with the next working move
+        let mut adapted = square();
+        adapted.path.insert(4, Move::Feed(5e-3));
+        let grbl = PostProcessor::new(Dialect::Grbl).write(&adapted, &config);
+        assert!(grbl.contains("G1 X20.000 Y10.000 F600.0\nG1 X20.000 Y20.000 F300.0\n"));
+
+        let mut linuxcnc = PostProcessor::new(Dialect::LinuxCnc);
+        assert!(linuxcnc.write(&square(), &config).contains("G64 P0.0100\n"));
+        let mut offset = test_config("{}").unwrap();
+        offset.placement.work_offset = Some(3);
+        let program = linuxcnc.write(&square(), &offset);
+        assert!(program.starts_with("%\n"));
+        assert!(program.contains("\nG56\nG21 "));
+        assert!(linuxcnc.write(&square(), &config).contains("G0 X10.0000 Y10.0000\n"));
+        linuxcnc.path_blending = false;
+        assert!(!linuxcnc.write(&square(), &config).contains("G64"));
+
+        let marlin = PostProcessor::new(Dialect::Marlin).write(&square(), &config);
+        assert!(marlin.starts_with("; generated for Marlin\nN1 M110*"));
+        for (n, line) in marlin.lines().skip(1).enumerate() {
+            let (content, checksum) = line.split_once('*').unwrap();
+            assert!(content.starts_with(&format!("N{} ", n + 1)));
+            assert_eq!(checksum.parse::<u8>().unwrap(), content.bytes().fold(0, |a, b| a ^ b));
+        }
+    }
+
+    #[test]
+    fn test_arcs() {
+        // a half circle of radius 10 mm in 64 segments, counter-clockwise
+        let mut path = Path{x_init:0.0, y_init:0.0, z_init:-1e-3, path:vec![Move::FXYmove(1e-2, 0.0)]};
+        for k in 1..=64 {
+            let a = std::f64::consts::PI * k as f64 / 64.0;
+            path.path.push(Move::XYmove(1e-2 * a.cos(), 1e-2 * a.sin()));
+        }
+
+        let instructions = fit_arcs(&path, 1e-5);
+        assert_eq!(instructions.len(), 2);
+        match instructions[1] {
+            Instruction::Arc{end, center, clockwise} => {
+                assert!((end - Vec2::new(-1e-2, 0.0)).norm() < 1e-9);
+                assert!(center.norm() < 1e-9);
+                assert!(!clockwise);
+            },
+            _ => panic!("the half circle must be an arc")
+        }
+
+        let mut post = PostProcessor::new(Dialect::Grbl);
+        assert!(post.write(&path, &test_config("{}").unwrap()).contains("G3 X-10.000 Y0.000 I-10.000 J0.000"));
+        post.arcs = false;
+        assert!(!post.write(&path, &test_config("{}").unwrap()).contains("G3"));
+
+        // a square is not fitted by an arc
+        assert_eq!(fit_arcs(&square(), 1e-5).len(), square().path.len());
+    }
+}
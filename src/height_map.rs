@@ -2,6 +2,33 @@ use image::{Rgba, Rgb, Pixel, DynamicImage, RgbImage, Luma, ImageFormat::Png};
 use crate::parse_config::ToolShape;
 use rayon::prelude::*;
 
+/// the interpolation used to compute the value of a map between its pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// value of the nearest pixel
+    Nearest,
+    /// linear interpolation between the four nearest pixels
+    Bilinear,
+    /// cubic (Catmull-Rom) interpolation between the sixteen nearest pixels
+    Bicubic
+}
+
+/// the value of the pixels outside of a map, used by the interpolation near the borders
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// value of the nearest pixel of the map
+    Clamp,
+    /// `0.0`, the top of the material
+    Zero,
+    /// a given value
+    Fill(f64)
+}
+
+/// Catmull-Rom cubic interpolation of `p1` and `p2` at `t` in `[0, 1]`
+fn cubic(p0:f64, p1:f64, p2:f64, p3:f64, t:f64) -> f64 {
+    p1 + 0.5 * t * (p2 - p0 + t * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3 + t * (3.0 * (p1 - p2) + p3 - p0)))
+}
+
 pub struct HeightMap {
     buffer : Vec<f64>,
     width : usize,
@@ -10,11 +37,7 @@ pub struct HeightMap {
 
 impl HeightMap {
     pub fn new(width : usize, height: usize) -> Self {
-        let mut buffer = Vec::with_capacity(width * height);
-
-        for _ in 0..width*height {
-            buffer.push(0.0);
-        }
+        let buffer = vec![0.0; width * height];
 
         HeightMap {
             buffer,
@@ -194,6 +217,65 @@ impl HeightMap {
         self
     }
 
+    /// return the value of the pixel, the coordinates are clamped to the borders of the map
+    pub fn get_clamped(&self, x:isize, y:isize) -> f64 {
+        let x = x.clamp(0, self.get_width() as isize - 1) as usize;
+        let y = y.clamp(0, self.get_height() as isize - 1) as usize;
+        self.unsafe_get(x, y)
+    }
+
+    /// return a new map such that each pixel is computed by `f` from the source map
+    /// and its coordinates, in parallel
+    pub fn par_map_pixels<F>(&self, f:F) -> Self
+        where
+            F: Fn(&Self, isize, isize) -> f64 + Sync
+    {
+        let (width, height) = (self.get_width(), self.get_height());
+        let mut buffer = vec![0.0; width * height];
+
+        buffer.par_iter_mut().enumerate().for_each(|(addr, p)| {
+            *p = f(self, (addr / height) as isize, (addr % height) as isize);
+        });
+
+        HeightMap::new_with_buffer(width, height, buffer)
+    }
+
+    /// return the value of the pixel `(x, y)`, or the value defined by `edge`
+    /// if it is outside of the map
+    pub fn get_edge(&self, x:isize, y:isize, edge:Edge) -> f64 {
+        if x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height {
+            self.unsafe_get(x as usize, y as usize)
+        } else {
+            match edge {
+                Edge::Clamp => self.get_clamped(x, y),
+                Edge::Zero => 0.0,
+                Edge::Fill(f) => f
+            }
+        }
+    }
+
+    /// return the value of the map at the continuous coordinates `(x, y)`, where
+    /// the pixel `(i, j)` is at `(i as f64, j as f64)`, the pixels outside
+    /// of the map are defined by `edge`
+    pub fn sample(&self, x:f64, y:f64, interpolation:Interpolation, edge:Edge) -> f64 {
+        let (i, j) = (x.floor() as isize, y.floor() as isize);
+        let (tx, ty) = (x - x.floor(), y - y.floor());
+        let get = |i:isize, j:isize| self.get_edge(i, j, edge);
+
+        match interpolation {
+            Interpolation::Nearest => get(x.round() as isize, y.round() as isize),
+            Interpolation::Bilinear => {
+                let top = get(i, j) * (1.0 - tx) + get(i+1, j) * tx;
+                let bottom = get(i, j+1) * (1.0 - tx) + get(i+1, j+1) * tx;
+                top * (1.0 - ty) + bottom * ty
+            },
+            Interpolation::Bicubic => {
+                let row = |j:isize| cubic(get(i-1, j), get(i, j), get(i+1, j), get(i+2, j), tx);
+                cubic(row(j-1), row(j), row(j+1), row(j+2), ty)
+            }
+        }
+    }
+
     pub fn get_default(&self, x:usize, y:usize) -> f64 {
         if x < self.width && y < self.height {self.unsafe_get(x, y)}
         else {0.0}
@@ -204,16 +286,14 @@ impl HeightMap {
 
         for i in 0..self.width {
             for j in 0..self.height {
-                let mut color = (self.get(i, j) - min) / (max - min);
-                if color > 1.0 {color = 1.0;}
-                if color < 0.0 {color = 0.0;}
+                let color = ((self.get(i, j) - min) / (max - min)).clamp(0.0, 1.0);
 
                 let pixel = Luma::<u8>::from_slice(&[(255.0 * color) as usize as u8]).to_rgb();
                 rgb.put_pixel(i as u32, j as u32, pixel);
             }
         }
 
-        if let Err(_) = DynamicImage::ImageRgb8(rgb).save_with_format(path, Png){
+        if DynamicImage::ImageRgb8(rgb).save_with_format(path, Png).is_err() {
             Err("unable to save the image".to_string())
         } else {Ok(())}
 
@@ -280,4 +360,27 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_sample() {
+        let hmap = HeightMap::new_with_buffer(2, 2, vec![
+            -1.0, -2.0,
+            -3.0, -4.0
+        ]);
+
+        let eq = |x1:f64, x2:f64| x1 < x2 + 1e-9 && x2 < x1 + 1e-9;
+
+        assert!(eq(hmap.sample(0.5, 0.5, Interpolation::Bilinear, Edge::Clamp), -2.5));
+        assert!(eq(hmap.sample(0.25, 0.0, Interpolation::Bilinear, Edge::Clamp), -1.5));
+        assert!(eq(hmap.sample(0.6, 0.4, Interpolation::Nearest, Edge::Clamp), -3.0));
+
+        // the bicubic interpolation is symmetric between the pixels
+        assert!(eq(hmap.sample(0.5, 0.5, Interpolation::Bicubic, Edge::Clamp), -2.5));
+
+        // the borders
+        assert!(eq(hmap.sample(-0.5, 0.0, Interpolation::Bilinear, Edge::Clamp), -1.0));
+        assert!(eq(hmap.sample(-0.5, 0.0, Interpolation::Bilinear, Edge::Zero), -0.5));
+        assert!(eq(hmap.sample(1.5, 1.0, Interpolation::Bilinear, Edge::Fill(-6.0)), -5.0));
+        assert!(eq(hmap.get_f64(-0.4, 1.0), -2.0));
+    }
 }
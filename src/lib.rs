@@ -1,6 +1,25 @@
 // pub mod parse_json;
 pub mod parse_config;
 pub mod parse_image;
+pub mod parse_svg;
+pub mod parse_dxf;
 pub mod height_map;
+pub mod preprocess;
+pub mod transform;
 pub mod bit_map;
+pub mod coordinates;
+pub mod affine;
+pub mod region;
+pub mod contour;
+pub mod offset;
+pub mod entry;
+pub mod feed;
+pub mod raster;
+pub mod gcode;
+#[cfg(unix)]
+pub mod sender;
+pub mod machine;
 pub mod segment;
+pub mod predicates;
+pub mod travel;
+pub mod path_json;
@@ -0,0 +1,176 @@
+use std::fmt;
+
+use crate::bit_map::{Move, Path};
+use crate::parse_config::Config;
+
+/// the capacities of a machine, the coordinates are in `m` in the machine coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MachineProfile {
+    /// smallest reachable coordinates along x, y and z
+    pub min : [f64; 3],
+
+    /// largest reachable coordinates along x, y and z
+    pub max : [f64; 3],
+
+    /// maximum speed in `m / s` along x, y and z
+    pub max_feed : [f64; 3],
+
+    /// maximum speed of the spindle in rotations per minute
+    pub max_spindle_speed : f64,
+
+    /// machine coordinates of the origin of the paths
+    pub work_offset : [f64; 3]
+}
+
+/// a move or a setting beyond the capacities of the machine
+#[derive(Clone, Debug, PartialEq)]
+pub enum Violation {
+    /// the move of index `index` reaches `position` (in machine coordinates)
+    /// outside of the travel along the axis `axis`
+    Travel{index:usize, position:[f64; 3], axis:char},
+    /// the speed `name` of the configuration exceeds the maximum speed along the axis `axis`
+    Feed{name:&'static str, speed:f64, max:f64, axis:char},
+    /// the move of index `index` moves along the axis `axis` faster than its maximum speed
+    MoveFeed{index:usize, speed:f64, max:f64, axis:char},
+    /// the speed of the spindle exceeds the maximum speed
+    Spindle{speed:f64, max:f64}
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Travel{index, position, axis} => write!(
+                f, "the move {} reaches ({}, {}, {}) out of the travel along {}",
+                index, position[0], position[1], position[2], axis
+            ),
+            Violation::Feed{name, speed, max, axis} => write!(
+                f, "the {} {} m/s exceeds the maximum feed {} m/s along {}", name, speed, max, axis
+            ),
+            Violation::MoveFeed{index, speed, max, axis} => write!(
+                f, "the move {} goes at {} m/s along {}, more than the maximum feed {} m/s", index, speed, axis, max
+            ),
+            Violation::Spindle{speed, max} => write!(
+                f, "the spindle speed {} rpm exceeds the maximum speed {} rpm", speed, max
+            )
+        }
+    }
+}
+
+const AXES : [char; 3] = ['x', 'y', 'z'];
+
+impl MachineProfile {
+    /// return the violations of the travel limits by the moves of a path, and of the maximum
+    /// feeds and spindle speed by the speeds of the configuration used to write it
+    pub fn check(&self, path:&Path, config:&Config) -> Vec<Violation> {
+        let mut out = vec![];
+
+        // the speeds of the configuration, the fly speed is not written in the program,
+        // the rapid moves go at the speed set in the controller
+        let feeds = [
+            ("horizontal work speed", config.horizontal_work_speed, &[0, 1][..]),
+            ("vertical speed", config.vectical_speed, &[2][..])
+        ];
+        for (name, speed, axes) in feeds {
+            for axis in axes {
+                if speed > self.max_feed[*axis] {
+                    out.push(Violation::Feed{name, speed, max:self.max_feed[*axis], axis:AXES[*axis]});
+                }
+            }
+        }
+
+        if let Some(speed) = config.spindle_speed {
+            if speed > self.max_spindle_speed {
+                out.push(Violation::Spindle{speed, max:self.max_spindle_speed});
+            }
+        }
+
+        // the positions reached by the moves
+        let mut position = [path.x_init, path.y_init, path.z_init];
+        let mut work_speed = config.horizontal_work_speed;
+        for (index, m) in path.path.iter().enumerate() {
+            let previous = position;
+            match *m {
+                Move::XYmove(x, y) | Move::FXYmove(x, y) => position = [x, y, position[2]],
+                Move::Zmove(z) => position[2] = z,
+                Move::XYZmove(x, y, z) => position = [x, y, z],
+                Move::Feed(speed) => {
+                    work_speed = speed;
+                    for (max, axis) in self.max_feed.iter().zip(AXES).take(2) {
+                        if speed > *max {
+                            out.push(Violation::MoveFeed{index, speed, max:*max, axis});
+                        }
+                    }
+                }
+            }
+
+            let machine = [0, 1, 2].map(|i| position[i] + self.work_offset[i]);
+            for axis in 0..3 {
+                if machine[axis] < self.min[axis] || machine[axis] > self.max[axis] {
+                    out.push(Violation::Travel{index, position:machine, axis:AXES[axis]});
+                    break;
+                }
+            }
+
+            // the component along z of a descent at the work speed
+            if let Move::XYZmove(_, _, _) = m {
+                let d = [0, 1, 2].map(|i| position[i] - previous[i]);
+                let length = f64::sqrt(d[0] * d[0] + d[1] * d[1] + d[2] * d[2]);
+                if length > 0.0 {
+                    let speed = work_speed * d[2].abs() / length;
+                    if speed > self.max_feed[2] {
+                        out.push(Violation::MoveFeed{index, speed, max:self.max_feed[2], axis:'z'});
+                    }
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::machine::*;
+    use crate::parse_config::test_config;
+
+    fn config(work_speed:f64, spindle:f64) -> Config {
+        test_config(&format!(r#"{{"horizontal work speed" : {}, "spindle speed" : {}}}"#, work_speed, spindle)).unwrap()
+    }
+
+    #[test]
+    fn test_check() {
+        let machine = MachineProfile{
+            min:[0.0, 0.0, -0.05],
+            max:[0.4, 0.2, 0.0],
+            max_feed:[0.05, 0.05, 0.01],
+            max_spindle_speed:24000.0,
+            work_offset:[0.1, 0.1, -0.01]
+        };
+
+        let path = Path{x_init:0.0, y_init:0.0, z_init:0.0, path:vec![
+            Move::Zmove(2e-3),
+            Move::FXYmove(0.15, 0.05),
+            Move::Zmove(-1e-3),
+            Move::XYmove(0.25, 0.05),
+            Move::XYZmove(0.25, 0.06, -2e-3)
+        ]};
+
+        // the fly speed of the configuration is faster than the machine, but it is not
+        // written in the program
+        assert_eq!(machine.check(&path, &config(1e-2, 12000.0)), vec![]);
+
+        // the cut goes out of the travel along y
+        let mut far = path.clone();
+        far.path[3] = Move::XYmove(0.25, 0.15);
+        let violations = machine.check(&far, &config(1e-2, 12000.0));
+        assert_eq!(violations.len(), 1);
+        assert!(matches!(violations[0], Violation::Travel{index:3, axis:'y', ..}));
+        assert!(violations[0].to_string().contains("the move 3"));
+
+        // the work speed is too fast along x, y and for the descent along z
+        let violations = machine.check(&path, &config(0.2, 30000.0));
+        assert!(violations.contains(&Violation::Feed{name:"horizontal work speed", speed:0.2, max:0.05, axis:'x'}));
+        assert!(violations.contains(&Violation::Spindle{speed:30000.0, max:24000.0}));
+        assert!(violations.iter().any(|v| matches!(v, Violation::MoveFeed{index:4, ..})));
+    }
+}
@@ -2,28 +2,37 @@ use image::{Rgb, Pixel};
 use rust_gcode::parse_config::*;
 use rust_gcode::parse_image::parse_image;
 use rust_gcode::bit_map::*;
+use rust_gcode::transform::Extent;
 
 pub fn main() {
     let args : Vec<String> = std::env::args().collect();
-    let (_config_file, _hmap_file) = get_path(&args);
+    let (config_file, hmap_file) = get_path(&args);
 
-    let _config = Config::new(&_config_file).unwrap();
+    let config = Config::new(&config_file).unwrap();
 
-    println!("{}", _config.fly_z);
+    println!("{}", config.fly_z);
 
 
     let hmap1 = parse_image(
-        "./test_png_image.png",
-        Rgb::from_slice(&[255, 255, 255]).clone()
+        &hmap_file,
+        *Rgb::from_slice(&[255, 255, 255])
     ).unwrap();
 
+    let (mut hmap1, extent) = hmap1.transforms(&config.transforms, Extent::new(config.width, config.height)).unwrap();
+
+    hmap1.preprocess(&config.preprocessing);
+
+    if config.normalizing {
+        hmap1.normalize(config.normalizing_clip);
+    }
+
     println!("Hello, world! {}", hmap1.get(0, 0));
 
 
-    let depth = 0.002;
+    let depth = config.depth;
 
     // adapt the hmap with the shape of the tool for under-approximate the final shape
-    let hmap2 = hmap1.generate_tool_hmap(0.2, 0.1, depth, ToolShape::Ball(0.0012));
+    let hmap2 = hmap1.generate_tool_hmap(extent.width, extent.height, depth, config.tool_shape);
 
     hmap2.save(-depth, 0.0, "test_hmap.png").expect("unable to save the height map");
     let bmap = BitMap::from_height_map(&hmap2, -depth * 0.5);
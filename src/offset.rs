@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+use crate::bit_map::{BitMap, Move, Path, PathAlgo};
+use crate::coordinates::{CoordinateMap, Placement};
+use crate::predicates::orientation;
+use crate::region::Connectivity;
+use crate::segment::{orient_regions, BoundingBox, Intersection, Polygon, Segment, Vec2};
+
+/// the shape of an offset around the corners it moves away from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Join {
+    /// an arc of circle centered on the corner
+    Round,
+    /// the two edges extended until they meet, squared if they meet farther from
+    /// the corner than the limit times the offset distance
+    Miter(f64),
+    /// the corner cut at the offset distance, perpendicular to its bisector
+    Square
+}
+
+/// return the unit normal on the right of the direction `t`
+fn right_normal(t:Vec2) -> Vec2 {
+    Vec2::new(t.get_y(), -t.get_x()) * (1.0 / t.norm())
+}
+
+/// push the points strictly between `p + n0 * d` and `p + n1 * d` joining the offsets
+/// of the edges `t0` and `t1` around the corner `p`
+fn push_join(p:Vec2, t0:Vec2, t1:Vec2, d:f64, join:Join, tolerance:f64, out:&mut Vec<Vec2>) {
+    let (n0, n1) = (right_normal(t0), right_normal(t1));
+    let reverse = t0.cross(t1) == 0.0;
+
+    match join {
+        Join::Round => {
+            let (a, b) = (n0 * d, n1 * d);
+            let sweep = if reverse {std::f64::consts::PI * d.signum()} else {f64::atan2(a.cross(b), a * b)};
+            let step = if tolerance < d.abs() {2.0 * f64::acos(1.0 - tolerance / d.abs())} else {std::f64::consts::FRAC_PI_2};
+            let k = (sweep.abs() / step).ceil() as usize;
+            for j in 1..k {
+                let (s, c) = (sweep * j as f64 / k as f64).sin_cos();
+                out.push(p + Vec2::new(c * a.get_x() - s * a.get_y(), s * a.get_x() + c * a.get_y()));
+            }
+        },
+        Join::Miter(limit) if !reverse && f64::sqrt(2.0 / (1.0 + n0 * n1)) <= limit => {
+            out.push(p + (n0 + n1) * (d / (1.0 + n0 * n1)));
+        },
+        _ => {
+            // the cut is at the distance `|d|` from the corner along the bisector `u`
+            let u = if reverse {t0 * (1.0 / t0.norm())} else {
+                let b = (n0 + n1) * d.signum();
+                b * (1.0 / b.norm())
+            };
+            let q = u * d.abs();
+            let s0 = (q - n0 * d) * u / (t0 * (1.0 / t0.norm()) * u);
+            let s1 = (q - n1 * d) * u / (t1 * (1.0 / t1.norm()) * u);
+            out.push(p + n0 * d + t0 * (s0 / t0.norm()));
+            out.push(p + n1 * d + t1 * (s1 / t1.norm()));
+        }
+    }
+}
+
+/// return the raw offset of a closed polygon with the region on its left, the edges
+/// moved by `d` on their right and linked by the joins, or through the corner where
+/// the offsets of two edges overlap, the result may intersect itself
+fn raw_offset(polygon:&Polygon, d:f64, join:Join, tolerance:f64) -> Vec<Vec2> {
+    let points = polygon.points();
+    let n = points.len();
+    let mut out = vec![];
+
+    for i in 0..n {
+        let (p0, p1, p2) = (points[i], points[(i+1) % n], points[(i+2) % n]);
+        let (t0, t1) = (p1 - p0, p2 - p1);
+        let normal = right_normal(t0) * d;
+
+        out.push(p0 + normal);
+        out.push(p1 + normal);
+
+        let turn = t0.cross(t1);
+        if turn * d > 0.0 || (turn == 0.0 && t0 * t1 < 0.0) {
+            push_join(p1, t0, t1, d, join, tolerance, &mut out);
+        } else if turn != 0.0 {
+            out.push(p1);
+        }
+    }
+
+    out.dedup();
+    out
+}
+
+/// remove the consecutive repeated vertices and the aligned vertices of a closed polyline
+fn simplify(points:Vec<Vec2>) -> Vec<Vec2> {
+    let mut points = points;
+    points.dedup();
+    if points.len() > 1 && points[0] == points[points.len()-1] {points.pop();}
+
+    let n = points.len();
+    let mut out : Vec<Vec2> = vec![];
+    for i in 0..n {
+        let prev = out.last().copied().unwrap_or(points[(i + n - 1) % n]);
+        if orientation(prev, points[i], points[(i+1) % n]) != 0 {out.push(points[i]);}
+    }
+    out
+}
+
+/// maximum number of cells along a side of a `Grid`
+const MAX_GRID_CELLS : usize = 1024;
+
+/// a uniform grid over segments, each cell lists the segments whose bounding box
+/// expanded by `margin` meets the cell
+struct Grid {
+    min : Vec2,
+    cell : f64,
+    width : usize,
+    height : usize,
+    cells : Vec<Vec<usize>>
+}
+
+impl Grid {
+    fn new(segments:&[Segment], margin:f64, cell:f64) -> Self {
+        let points : Vec<Vec2> = segments.iter().flat_map(|s| [s.source(), s.target()]).collect();
+        let bbox = match BoundingBox::from_points(&points) {
+            Some(bbox) => bbox,
+            None => return Grid{min:Vec2::new(0.0, 0.0), cell:1.0, width:0, height:0, cells:vec![]}
+        };
+
+        let margin = Vec2::new(margin, margin);
+        let min = bbox.min - margin;
+        let size = f64::max(bbox.width(), bbox.height()) + 2.0 * margin.get_x();
+        let cell = f64::max(cell, size / MAX_GRID_CELLS as f64);
+        let width = ((bbox.width() + 2.0 * margin.get_x()) / cell) as usize + 1;
+        let height = ((bbox.height() + 2.0 * margin.get_y()) / cell) as usize + 1;
+
+        let mut cells = vec![vec![]; width * height];
+        for (k, s) in segments.iter().enumerate() {
+            let b = BoundingBox::from_points(&[s.source(), s.target()]).unwrap();
+            let (lo, hi) = (b.min - margin - min, b.max + margin - min);
+            let (x0, y0) = ((lo.get_x() / cell) as usize, (lo.get_y() / cell) as usize);
+            let (x1, y1) = ((hi.get_x() / cell) as usize, (hi.get_y() / cell) as usize);
+            for x in x0..=usize::min(x1, width - 1) {
+                for y in y0..=usize::min(y1, height - 1) {
+                    cells[x + y * width].push(k);
+                }
+            }
+        }
+
+        Grid{min, cell, width, height, cells}
+    }
+
+    /// return the segments whose bounding box expanded by the margin may contain `p`
+    fn near(&self, p:Vec2) -> &[usize] {
+        let (x, y) = ((p.get_x() - self.min.get_x()) / self.cell, (p.get_y() - self.min.get_y()) / self.cell);
+        if x < 0.0 || y < 0.0 || x as usize >= self.width || y as usize >= self.height {return &[];}
+        &self.cells[x as usize + y as usize * self.width]
+    }
+}
+
+/// return the closed loops chained from directed segments, following the segment
+/// starting where the previous one ends, the unclosed chains are dropped
+fn chain(pieces:&[(Vec2, Vec2)]) -> Vec<Vec<Vec2>> {
+    let key = |p:Vec2| (p.get_x().to_bits(), p.get_y().to_bits());
+    let mut starts : HashMap<(u64, u64), Vec<usize>> = HashMap::new();
+    for (i, piece) in pieces.iter().enumerate() {
+        starts.entry(key(piece.0)).or_default().push(i);
+    }
+
+    let mut used = vec![false; pieces.len()];
+    let mut loops = vec![];
+    for first in 0..pieces.len() {
+        if used[first] {continue;}
+        used[first] = true;
+
+        let mut points = vec![pieces[first].0];
+        let mut current = pieces[first].1;
+        let closed = loop {
+            if key(current) == key(points[0]) {break true;}
+            points.push(current);
+            let next = starts.get(&key(current)).and_then(|list| list.iter().copied().find(|i| !used[*i]));
+            match next {
+                Some(next) => {
+                    used[next] = true;
+                    current = pieces[next].1;
+                },
+                None => break false
+            }
+        };
+        if closed {loops.push(points);}
+    }
+    loops
+}
+
+/// return the boundaries of the region of the polygons (the points inside an odd number
+/// of them) grown by `distance` if it is positive or shrunk by `-distance` if it is negative,
+/// the arcs of the round joins are within `tolerance` of the circles; the self-intersections
+/// of the offset are removed and the region may split or merge into several loops, oriented
+/// with the region on their left (the outer loops counter-clockwise with the y-axis pointing up)
+pub fn offset(polygons:&[Polygon], distance:f64, join:Join, tolerance:f64) -> Vec<Polygon> {
+    let polygons : Vec<Polygon> = polygons.iter().map(|p| Polygon::new(simplify(p.points().to_vec())))
+        .filter(|p| p.len() >= 3).collect();
+    let polygons = orient_regions(&polygons);
+    if distance == 0.0 {return polygons;}
+
+    let boundary : Vec<Segment> = polygons.iter().flat_map(|p| p.segments().collect::<Vec<Segment>>()).collect();
+    let segments : Vec<Segment> = polygons.iter().flat_map(|polygon| {
+        let raw = raw_offset(polygon, distance, join, tolerance);
+        let n = raw.len();
+        (0..n).map(move |i| Segment::new(raw[i], raw[(i+1) % n])).collect::<Vec<Segment>>()
+    }).filter(|s| s.source() != s.target()).collect();
+
+    // split the segments where they cross, sweeping them along the x-axis
+    let boxes : Vec<BoundingBox> = segments.iter()
+        .map(|s| BoundingBox::from_points(&[s.source(), s.target()]).unwrap()).collect();
+    let mut order : Vec<usize> = (0..segments.len()).collect();
+    order.sort_by(|a, b| boxes[*a].min.get_x().total_cmp(&boxes[*b].min.get_x()));
+
+    let mut splits : Vec<Vec<Vec2>> = vec![vec![]; segments.len()];
+    for (k, &i) in order.iter().enumerate() {
+        for &j in order[k+1..].iter() {
+            if boxes[j].min.get_x() > boxes[i].max.get_x() {break;}
+            if !boxes[i].intersects(&boxes[j]) {continue;}
+            let points = match segments[i].intersection(&segments[j]) {
+                Intersection::None => continue,
+                Intersection::Point(p) => vec![p],
+                Intersection::Overlap(s) => vec![s.source(), s.target()]
+            };
+            splits[i].extend(points.iter().copied());
+            splits[j].extend(points);
+        }
+    }
+
+    // keep the pieces at the offset distance from the polygons, on the side of the offset,
+    // only the boundary segments near a piece and the polygons around it are checked
+    let limit = distance.abs() - tolerance - 1e-9 * distance.abs();
+    let grid = Grid::new(&boundary, f64::max(limit, 0.0), distance.abs());
+    let polygon_boxes : Vec<BoundingBox> = polygons.iter().map(|p| p.bounding_box().unwrap()).collect();
+    let mut pieces = vec![];
+    for (s, mut points) in segments.iter().zip(splits) {
+        let (a, t) = (s.source(), s.target() - s.source());
+        points.sort_by(|p, q| ((*p - a) * t).total_cmp(&((*q - a) * t)));
+        points.insert(0, a);
+        points.push(s.target());
+        points.dedup();
+
+        for w in points.windows(2) {
+            let middle = (w[0] + w[1]) * 0.5;
+            let inside = polygons.iter().zip(polygon_boxes.iter())
+                .filter(|(p, b)| b.contains(middle) && p.contains(middle)).count() % 2 == 1;
+            if inside != (distance < 0.0) {continue;}
+            if grid.near(middle).iter().all(|&k| boundary[k].distance_to(middle) >= limit) {pieces.push((w[0], w[1]));}
+        }
+    }
+
+    chain(&pieces).into_iter()
+        .map(simplify)
+        .filter(|points| points.len() >= 3)
+        .map(Polygon::new)
+        .filter(|polygon| polygon.signed_area().abs() > tolerance * tolerance)
+        .collect()
+}
+
+/// return the loops of a concentric pocket of the region of the polygons, the region shrunk
+/// by `first`, then by `first + step`, `first + 2 * step`... while it is not empty,
+/// from the outside to the inside
+pub fn concentric(polygons:&[Polygon], first:f64, step:f64, join:Join, tolerance:f64) -> Vec<Vec<Polygon>> {
+    assert!(step > 0.0);
+    let mut levels = vec![];
+    loop {
+        let level = offset(polygons, -(first + levels.len() as f64 * step), join, tolerance);
+        if level.is_empty() {return levels;}
+        levels.push(level);
+    }
+}
+
+/// the concentric pocket strategy: clear the regions of `true` pixels of a bit map with
+/// loops parallel to their boundaries, the boundaries shrunk by the radius of the tool, then
+/// by the stepover, the inner loops first
+pub struct Pocket {
+    /// size in `m` of a pixel along the x-axis and the y-axis
+    pub pixel_size : (f64, f64),
+
+    /// depth in `m` of the pocket, the minimum value of z is `-depth`
+    pub depth : f64,
+
+    /// height of the tool during the rapid moves
+    pub fly_z : f64,
+
+    /// radius in `m` of the tool, the distance between the first loop and the boundary
+    pub tool_radius : f64,
+
+    /// distance in `m` between two loops, smaller than the diameter of the tool
+    pub stepover : f64,
+
+    /// shape of the loops around the corners of the region
+    pub join : Join,
+
+    /// connectivity of the regions of `true` pixels
+    pub connectivity : Connectivity,
+
+    /// distance in `m` allowed between the round joins and their arcs
+    pub tolerance : f64,
+
+    /// placement of the paths relative to the bit map, the heights are given from the top of the stock
+    pub placement : Placement
+}
+
+impl Pocket {
+    pub fn new(pixel_size:(f64, f64), depth:f64, fly_z:f64, tool_radius:f64, stepover:f64) -> Self {
+        Pocket{
+            pixel_size, depth, fly_z, tool_radius, stepover,
+            join:Join::Round,
+            connectivity:Connectivity::Four,
+            tolerance:1e-5,
+            placement:Placement::new()
+        }
+    }
+}
+
+impl PathAlgo for Pocket {
+    fn from_bit_map(&self, bit_map:&BitMap, x_init:f64, y_init:f64, z_init:f64) -> Path {
+        let map = CoordinateMap::new(self.placement, self.pixel_size, (bit_map.get_width(), bit_map.get_height()));
+        let polygons : Vec<Polygon> = bit_map.par_connected_contours(self.connectivity).iter()
+            .map(|polygon| Polygon::new(polygon.points().iter().map(|p| map.to_path(*p)).collect()))
+            .collect();
+
+        let levels = concentric(&polygons, self.tool_radius, self.stepover, self.join, self.tolerance);
+
+        let mut path = vec![Move::Zmove(self.fly_z)];
+        for polygon in levels.iter().rev().flatten() {
+            let points = polygon.points();
+            path.push(Move::FXYmove(points[0].get_x(), points[0].get_y()));
+            path.push(Move::Zmove(-self.depth));
+            for p in points[1..].iter().chain(std::iter::once(&points[0])) {
+                path.push(Move::XYmove(p.get_x(), p.get_y()));
+            }
+            path.push(Move::Zmove(self.fly_z));
+        }
+
+        Path{x_init, y_init, z_init, path:map.map_heights(path)}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::offset::*;
+
+    fn rectangle(x0:f64, y0:f64, x1:f64, y1:f64) -> Polygon {
+        Polygon::new(vec![Vec2::new(x0, y0), Vec2::new(x1, y0), Vec2::new(x1, y1), Vec2::new(x0, y1)])
+    }
+
+    fn close(a:f64, b:f64, eps:f64) -> bool {f64::abs(a - b) < eps}
+
+    #[test]
+    fn test_joins() {
+        // a clockwise square is oriented before the offset
+        let square = vec![rectangle(0.0, 0.0, 10.0, 10.0).reversed()];
+
+        let round = offset(&square, 1.0, Join::Round, 1e-4);
+        assert_eq!(round.len(), 1);
+        assert!(round[0].is_counter_clockwise());
+        assert!(close(round[0].signed_area(), 140.0 + std::f64::consts::PI, 1e-2));
+
+        let miter = offset(&square, 1.0, Join::Miter(2.0), 1e-4);
+        assert_eq!(miter[0].len(), 4);
+        assert!(close(miter[0].signed_area(), 144.0, 1e-9));
+
+        // the corners are cut at the distance 1 from the square, a miter limit below
+        // sqrt(2) gives the same result
+        let corner = (f64::sqrt(2.0) - 1.0).powi(2);
+        for join in [Join::Square, Join::Miter(1.2)] {
+            let squared = offset(&square, 1.0, join, 1e-4);
+            assert_eq!(squared[0].len(), 8);
+            assert!(close(squared[0].signed_area(), 144.0 - 4.0 * corner, 1e-9));
+        }
+
+        let shrunk = offset(&square, -1.0, Join::Round, 1e-4);
+        assert_eq!(shrunk.len(), 1);
+        assert_eq!(shrunk[0].len(), 4);
+        assert!(close(shrunk[0].signed_area(), 64.0, 1e-9));
+        assert!(offset(&square, -5.5, Join::Round, 1e-4).is_empty());
+    }
+
+    #[test]
+    fn test_topology() {
+        // two squares linked by a thin corridor split when they are shrunk
+        let dumbbell = vec![Polygon::new(vec![
+            Vec2::new(0.0, 0.0), Vec2::new(4.0, 0.0), Vec2::new(4.0, 1.5), Vec2::new(8.0, 1.5),
+            Vec2::new(8.0, 0.0), Vec2::new(12.0, 0.0), Vec2::new(12.0, 4.0), Vec2::new(8.0, 4.0),
+            Vec2::new(8.0, 2.5), Vec2::new(4.0, 2.5), Vec2::new(4.0, 4.0), Vec2::new(0.0, 4.0)
+        ])];
+        assert_eq!(offset(&dumbbell, -0.25, Join::Round, 1e-4).len(), 1);
+        let split = offset(&dumbbell, -0.75, Join::Round, 1e-4);
+        assert_eq!(split.len(), 2);
+        // the squares shrunk, with the bulge between the arcs around the corners of the corridor
+        assert!(close(split[0].signed_area(), split[1].signed_area(), 1e-9));
+        assert!(split[0].signed_area() > 2.5 * 2.5 && split[0].signed_area() < 2.5 * 2.5 + 0.1);
+
+        // a square with a hole, the hole shrinks then disappears when it is grown
+        let ring = vec![rectangle(0.0, 0.0, 10.0, 10.0), rectangle(3.0, 3.0, 7.0, 7.0)];
+        let grown = offset(&ring, 1.0, Join::Miter(2.0), 1e-4);
+        assert_eq!(grown.len(), 2);
+        let mut areas : Vec<f64> = grown.iter().map(|p| p.signed_area()).collect();
+        areas.sort_by(|a, b| a.total_cmp(b));
+        assert!(close(areas[0], -4.0, 1e-9) && close(areas[1], 144.0, 1e-9));
+        assert_eq!(offset(&ring, 2.5, Join::Miter(2.0), 1e-4).len(), 1);
+
+        // the pocket of a square
+        let levels = concentric(&[rectangle(0.0, 0.0, 10.0, 10.0)], 1.0, 1.5, Join::Round, 1e-4);
+        assert_eq!(levels.len(), 3);
+        assert!(close(levels[2][0].signed_area(), 2.0 * 2.0, 1e-9));
+    }
+
+    #[test]
+    fn test_pocket() {
+        let mut bmap = BitMap::new(20, 20);
+        for x in 2..18 {
+            for y in 2..18 {
+                bmap.set(x, y, true);
+            }
+        }
+
+        let pocket = Pocket::new((1e-3, 1e-3), 1e-3, 2e-3, 1e-3, 1.5e-3);
+        let path = pocket.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        let loops = path.path.iter().filter(|m| matches!(m, Move::FXYmove(_, _))).count();
+        assert_eq!(loops, 5);
+
+        // the loops stay at the radius of the tool from the boundary
+        let boundary = bmap.contours()[0].clone();
+        let boundary = Polygon::new(boundary.points().iter().map(|p| *p * 1e-3).collect());
+        for m in path.path.iter() {
+            if let Move::XYmove(x, y) = m {
+                let p = Vec2::new(*x, *y);
+                assert!(boundary.contains(p));
+                assert!(boundary.closest_point(p).unwrap().distance(p) >= 1e-3 - 1e-5 - 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid() {
+        // a star with many edges, the grid finds all the segments near a point
+        let star = Polygon::new((0..40).map(|i| {
+            let (a, r) = (i as f64 * std::f64::consts::PI / 20.0, if i % 2 == 0 {10.0} else {3.0});
+            Vec2::new(r * a.cos(), r * a.sin())
+        }).collect());
+        let segments : Vec<Segment> = star.segments().collect();
+        let grid = Grid::new(&segments, 0.5, 0.5);
+
+        for i in 0..=60 {
+            for j in 0..=60 {
+                let p = Vec2::new(-12.0 + 0.4 * i as f64, -12.0 + 0.4 * j as f64);
+                for (k, s) in segments.iter().enumerate() {
+                    if s.distance_to(p) < 0.5 {assert!(grid.near(p).contains(&k));}
+                }
+            }
+        }
+    }
+}
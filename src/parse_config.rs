@@ -2,8 +2,14 @@ use std::{process::exit, fs::read_to_string};
 
 use json::parse;
 
+use crate::height_map::Interpolation;
+use crate::gcode::{CommentStyle, PostProcessor};
+use crate::machine::MachineProfile;
+use crate::coordinates::{Origin, Placement, ZReference};
+
 /// a description of the shape of the CNC bit
 /// the
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ToolShape {
     /// `Flat(r)` represent a flat CNC bit of rayon `r` in meter
     Flat(f64),
@@ -17,10 +23,10 @@ impl ToolShape {
     /// return the rayon of the CNC bit
     pub fn get_rayon(&self) -> f64 {
         match self {
-            ToolShape::Flat(r) => r,
-            ToolShape::Ball(r) => r,
-            ToolShape::V(r, _) => r
-        }.clone()
+            ToolShape::Flat(r) => *r,
+            ToolShape::Ball(r) => *r,
+            ToolShape::V(r, _) => *r
+        }
     }
 
     /// return the size of the CNC bit along the z-axis,
@@ -28,12 +34,139 @@ impl ToolShape {
     pub fn get_size(&self) -> f64 {
         match self {
             ToolShape::Flat(_) => 1.0,
-            ToolShape::Ball(r) => r.clone(),
-            ToolShape::V(r, t) => r.clone() * f64::tan(t.clone())
+            ToolShape::Ball(r) => *r,
+            ToolShape::V(r, t) => r * f64::tan(*t)
         }
     }
 }
 
+/// an operation applied to the height map imported from an image before
+/// the computation of the tool path, the levels are in `[0, 1]` from black to white
+#[derive(Clone, Debug, PartialEq)]
+pub enum Preprocess {
+    /// swap the white and black pixels
+    Invert,
+    /// `Gamma(g)` map each level `t` to `t^g`
+    Gamma(f64),
+    /// remap the levels with a piecewise linear curve given by `(input, output)` points
+    Curve(Vec<(f64, f64)>),
+    /// `Levels(low, high)` stretch the levels such that `low` becomes black and `high` white
+    Levels(f64, f64),
+    /// gaussian blur of standard deviation given in pixels
+    GaussianBlur(f64),
+    /// median blur on a square window of the given radius in pixels
+    MedianBlur(usize),
+    /// set the levels smaller than the threshold to black, and the others to white
+    Threshold(f64)
+}
+
+/// a geometric transformation of the height map imported from an image,
+/// the physical size of the map is updated along with it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transform {
+    /// `Crop(x, y, width, height)` keep the region of `width * height` pixels
+    /// whose top-left pixel is `(x, y)`
+    Crop(usize, usize, usize, usize),
+    /// `Pad(left, top, right, bottom, value)` add pixels of value `value` around the map
+    Pad(usize, usize, usize, usize, f64),
+    /// `Resample(width, height, interpolation)` resample the map to `width * height` pixels
+    Resample(usize, usize, Interpolation),
+    /// `Resolution(size, interpolation)` resample the map to pixels of size `size` in `m`
+    Resolution(f64, Interpolation),
+    /// `Rotate(angle, value, interpolation)` rotate the map by `angle` radians counter-clockwise,
+    /// the new corners are filled with `value`
+    Rotate(f64, f64, Interpolation),
+    /// reverse the map along the x-axis
+    MirrorX,
+    /// reverse the map along the y-axis
+    MirrorY,
+    /// `Fit(width, height, value)` scale the map to fit in a stock of size `width * height`
+    /// in `m` and center it, the margins are filled with `value`
+    Fit(f64, f64, f64)
+}
+
+/// the machining operation applied to the shapes of a layer of a vector input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    /// remove all the material inside the closed shapes
+    Pocket,
+    /// cut along the closed shapes, outside of them
+    Profile,
+    /// follow the shapes with the center of the tool
+    Engrave
+}
+
+/// operation and depth in `m` associated to a layer of a vector input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LayerOperation {
+    pub operation : Operation,
+    pub depth : f64
+}
+
+/// where the tabs are placed along each loop of a through-cut profile
+#[derive(Clone, Debug, PartialEq)]
+pub enum TabPlacement {
+    /// `Count(n)` place `n` tabs evenly along each loop
+    Count(usize),
+    /// `Spacing(d)` place a tab every `d` in `m` along each loop (at least one)
+    Spacing(f64),
+    /// place a tab at the point of the nearest loop closest to each position in `m`
+    Positions(Vec<(f64, f64)>)
+}
+
+/// the tabs holding a part to the stock during a through-cut
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tabs {
+    pub placement : TabPlacement,
+
+    /// length in `m` of a tab along the path
+    pub width : f64,
+
+    /// height in `m` of a tab from the bottom of the cut
+    pub height : f64,
+
+    /// the tabs are not placed where the radius of curvature of the path
+    /// is smaller than `min_radius` in `m`
+    pub min_radius : f64
+}
+
+/// the cutting direction relative to the material, for a spindle turning clockwise
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MillingDirection {
+    /// the material is on the right of the tool along its path
+    Climb,
+    /// the material is on the left of the tool along its path
+    Conventional
+}
+
+/// the way the tool enters the material instead of a straight plunge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    /// maximum angle in radian between the path of the tool and the horizontal plane
+    pub max_angle : f64,
+
+    /// radius in `m` of the helical entry, `0.0` to ramp along the cut only
+    pub helix_radius : f64
+}
+
+/// the speed of the working moves adapted to the material removed by the tool,
+/// to keep the load of the tool steady
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveFeed {
+    /// depth in `m` of a slot of the width of the tool cut at the horizontal work speed,
+    /// the speed is inversely proportional to the section of material removed
+    pub reference_depth : f64,
+
+    /// slowest speed in `m / s` of the working moves
+    pub min_speed : f64,
+
+    /// fastest speed in `m / s` of the working moves, in the light cuts and in the air
+    pub max_speed : f64,
+
+    /// size in `m` of the cells of the simulated stock
+    pub resolution : f64
+}
+
 /// configuration structure,
 /// deduced from the JSON input to the program
 pub struct Config {
@@ -65,6 +198,43 @@ pub struct Config {
     /// the deepest point
     pub normalizing : bool,
 
+    /// fraction of the darkest and of the lightest pixels ignored by the
+    /// normalization, in `[0, 0.5)`, these pixels are clamped
+    pub normalizing_clip : f64,
+
+    /// geometric transformations applied in order to the height map after its import
+    pub transforms : Vec<Transform>,
+
+    /// operations applied in order to the height map before the computation of the tool path
+    pub preprocessing : Vec<Preprocess>,
+
+    /// operation associated to each layer name of a vector input (DXF)
+    pub layers : Vec<(String, LayerOperation)>,
+
+    /// tabs left on the final passes of a through-cut contour, if any
+    pub tabs : Option<Tabs>,
+
+    /// ramp or helical entry in the material, straight plunges if `None`
+    pub entry : Option<Entry>,
+
+    /// speed of the working moves adapted to the engagement of the tool, constant if `None`
+    pub adaptive_feed : Option<AdaptiveFeed>,
+
+    /// cutting direction enforced on the loops and the raster lines, free if `None`
+    pub milling_direction : Option<MillingDirection>,
+
+    /// speed of the spindle in rotations per minute, if known
+    pub spindle_speed : Option<f64>,
+
+    /// translation of the paths into the G-code of the controller
+    pub post_processor : PostProcessor,
+
+    /// travel limits and maximum speeds of the machine, not checked if `None`
+    pub machine : Option<MachineProfile>,
+
+    /// origin, axes and zero of the z-axis of the paths relative to the image
+    pub placement : Placement,
+
 }
 
 pub fn help() -> String {
@@ -84,7 +254,32 @@ the input JSON must have the following format:
     "depth" : 1.5e-3,
     "width" : 1e-2,
     "height": 2e-2,
-    "normalizing" : "false"
+    "normalizing" : "false",
+    "normalizing clip" : 0.01,
+    "transforms" : [
+        {"transform" : "rotate", "angle" : 1.5707963},
+        {"transform" : "resolution", "pixel size" : 1e-4, "interpolation" : "bicubic"}
+    ],
+    "preprocessing" : [
+        {"operation" : "median blur", "radius" : 1},
+        {"operation" : "gamma", "value" : 2.2}
+    ],
+    "layers" : {
+        "outline" : {"operation" : "profile", "depth" : 3e-3}
+    },
+    "tabs" : {"count" : 4, "width" : 5e-3, "height" : 1e-3, "min radius" : 5e-3},
+    "entry" : {"max angle" : 0.05, "helix radius" : 2e-3},
+    "adaptive feed" : {"reference depth" : 1e-3, "min speed" : 5e-4, "max speed" : 4e-3},
+    "milling direction" : "climb",
+    "spindle speed" : 12000,
+    "post processor" : {"dialect" : "grbl", "precision" : 3},
+    "machine" : {
+        "travel" : {"x" : [0.0, 0.3], "y" : [0.0, 0.18], "z" : [-0.045, 0.0]},
+        "max feed" : {"x" : 0.083, "y" : 0.083, "z" : 0.016},
+        "max spindle speed" : 10000,
+        "work offset" : [0.1, 0.05, -0.03]
+    },
+    "placement" : {"origin" : "bottom left", "flip y" : true, "z zero" : "bed", "stock thickness" : 2e-2, "work offset" : 1}
 }
 
 with
@@ -98,12 +293,81 @@ with
 - "deep" is the maximum engraving depth as float in `m`
 - "width" is the size along the x-axis of the engraved object as float in `m`
 - "height" is the size along the y-axis of the engraved object as float in `m`
-- "normalizing", if "true" then the depth is normalized using the maximal depth in the input image
+- "normalizing", if "true" then the height map is rescaled such that its deepest point is at "depth"
+  and its highest point at zero
+- "normalizing clip" (optional, 0.0 by default) the fraction of the darkest and of the lightest pixels
+  ignored by the normalization, to avoid outliers
 - "flight height" :  height (along z-axis) of the CNC wick in the flight phases
+- "transforms" (optional) is a list of geometric transformations applied in order to the height map,
+  "width" and "height" are updated with the size of the transformed map:
+    . {"transform" : "crop", "x" : <pixel>, "y" : <pixel>, "width" : <pixels>, "height" : <pixels>}
+    . {"transform" : "pad", "left" : <pixels>, "top" : <pixels>, "right" : <pixels>, "bottom" : <pixels>}
+    . {"transform" : "resample", "width" : <pixels>, "height" : <pixels>}
+    . {"transform" : "resolution", "pixel size" : <size of a pixel in `m`>}
+    . {"transform" : "rotate", "angle" : <angle in radian, counter-clockwise>}
+    . {"transform" : "mirror", "axis" : "x" or "y"}
+    . {"transform" : "fit", "width" : <stock width in `m`>, "height" : <stock height in `m`>}
+  "resample", "resolution" and "rotate" accept an "interpolation": "nearest", "bilinear" (by default)
+  or "bicubic", "pad", "rotate" and "fit" accept the "value" of the new pixels (0.0 by default),
+  a crop must be inside the map as transformed by the previous transformations
+- "preprocessing" (optional) is a list of operations applied in order to the height map,
+  the levels are floats between 0.0 (black) and 1.0 (white):
+    . {"operation" : "invert"}
+    . {"operation" : "gamma", "value" : <exponent>}
+    . {"operation" : "curve", "points" : [[<input level>, <output level>], ...]}
+    . {"operation" : "levels", "low" : <level>, "high" : <level>}
+    . {"operation" : "gaussian blur", "sigma" : <standard deviation in pixels>}
+    . {"operation" : "median blur", "radius" : <radius in pixels>}
+    . {"operation" : "threshold", "level" : <level>}
+- "layers" (optional) maps the layer names of a vector input to an operation:
+    . "operation": "pocket", "profile" or "engrave"
+    . "depth": the depth of the operation as float in `m`
+- "tabs" (optional) leaves tabs holding the parts on the last passes of a contour:
+    . "count": the number of tabs on each loop, or "spacing": the distance in `m` between two tabs,
+      or "positions": a list of points [[<x>, <y>], ...] in `m`, each one placing a tab on the nearest loop
+    . "width": the length of a tab along the path as float in `m`
+    . "height": the height of a tab above the bottom of the cut as float in `m`
+    . "min radius" (optional, 0.0 by default): no tab is placed where the path turns with a smaller radius in `m`
+- "entry" (optional) replaces the straight plunges in the material, by default the tool plunges:
+    . "max angle": the maximum angle in radian of the descent with the horizontal plane
+    . "helix radius" (optional, 0.0 by default): the radius in `m` of a helical entry, tried before
+      a ramp along the cut when the helix fits in the region cut, 0.0 to disable it
+- "adaptive feed" (optional) adapts the speed of each working move to the material it removes in
+  a simulated stock, the speed is reduced in the heavy cuts and multiplied by cos(angle / 2) after a change
+  of direction of the working moves:
+    . "reference depth": the depth in `m` of a slot of the width of the tool cut at the "horizontal work speed"
+    . "min speed", "max speed": the range in `m / s` of the speed of the working moves
+    . "resolution" (optional, an eighth of the rayon of the tool by default): the size in `m` of the cells
+      of the simulated stock, enlarged on the large paths to keep about 16 million cells
+- "milling direction" (optional) enforces the cutting direction for a spindle turning clockwise,
+  "climb" or "conventional", the loops are run clockwise or counter-clockwise depending on the side
+  of the material and the raster lines are all cut in the same direction
+- "spindle speed" (optional) is the speed of the spindle in rotations per minute
+- "post processor" (optional) selects the G-code written for the controller:
+    . "dialect": "grbl" (by default), "linuxcnc", "marlin" or "mach3"
+    . "precision", "line numbers", "checksums", "arcs", "path blending" (optional) override the
+      number of decimals of the coordinates in `mm` and the features of the dialect
+    . "comments" (optional): "parentheses" or "semicolon"
+    . "header", "footer" (optional): the lines written before and after the moves, with the
+      placeholders {fly_z}, {tolerance} and {spindle}
+    . "tolerance" (optional): the distance in `m` allowed between the moves and the arcs fitted on them
+- "machine" (optional) describes the machine, the paths and the speeds are checked against it:
+    . "travel": the smallest and the largest machine coordinates in `m` along each axis
+    . "max feed": the maximum speed in `m / s` along each axis, the rapid moves are not checked,
+      they are written without a speed and the controller limits them
+    . "max spindle speed": the maximum speed of the spindle in rotations per minute
+    . "work offset" (optional, zero by default): the machine coordinates in `m` of the origin of the paths
+- "placement" (optional) places the paths relative to the image, by default the origin is the top-left
+  corner, the y-axis points down like the rows of the image and z is zero at the top of the stock:
+    . "origin" (optional): "top left", "top right", "bottom left", "bottom right" (the centre of the pixel
+      of this corner) or "center"
+    . "flip x", "flip y" (optional, false by default): reverse the x-axis or the y-axis of the paths
+    . "z zero" (optional): "surface" (by default) or "bed", with the "stock thickness" in `m`
+    . "work offset" (optional): the work coordinate system selected in the program, from 1 (G54) to 6 (G59)
 "#.to_string()
 }
 
-pub fn get_path(args: &Vec<String>) -> (String, String) {
+pub fn get_path(args: &[String]) -> (String, String) {
     let mut i = 1;
 
     let mut config_file : Option<String> = None;
@@ -145,6 +409,17 @@ pub fn get_path(args: &Vec<String>) -> (String, String) {
 }
 
 
+/// parse an interpolation name, bilinear by default
+fn parse_interpolation(value:&json::JsonValue) -> Option<Interpolation> {
+    match value.as_str() {
+        None if value.is_null() => Some(Interpolation::Bilinear),
+        Some("bilinear") => Some(Interpolation::Bilinear),
+        Some("nearest") => Some(Interpolation::Nearest),
+        Some("bicubic") => Some(Interpolation::Bicubic),
+        _ => None
+    }
+}
+
 impl Config {
     pub fn new(path:&str) -> Result<Self, String> {
         if let Ok(content) = read_to_string(path) {
@@ -191,19 +466,326 @@ impl Config {
         };
 
         let normalizing = if object["normalizing"] == "true" || object["normalizing"] == "false" {
-            object["normalizing"] == true
+            object["normalizing"] == "true"
+        } else if let Some(normalizing) = object["normalizing"].as_bool() {
+            normalizing
         } else {
             return Err(format!("don't find a valid normalization parameter in the file `{}`", path));
         };
 
+        let normalizing_clip = if object["normalizing clip"].is_null() {0.0} else {
+            match object["normalizing clip"].as_f64() {
+                Some(clip) if (0.0..0.5).contains(&clip) => clip,
+                _ => return Err(format!("dont find a valid normalizing clip in the file `{}`", path))
+            }
+        };
+
+
+        let mut transforms = vec![];
+        for (i, t) in object["transforms"].members().enumerate() {
+            let param = |name:&str| -> Result<f64, String> {
+                if let Some(data) = t[name].as_f64() {
+                    Ok(data)
+                } else {
+                    Err(format!("dont find a valid {} for the transform {} in the file `{}`", name, i, path))
+                }
+            };
+            let pixels = |name:&str| -> Result<usize, String> {
+                if let Some(data) = t[name].as_usize() {
+                    Ok(data)
+                } else {
+                    Err(format!("dont find a valid {} for the transform {} in the file `{}`", name, i, path))
+                }
+            };
+            let positive = |name:&str| -> Result<f64, String> {
+                match param(name)? {
+                    data if data > 0.0 => Ok(data),
+                    _ => Err(format!("dont find a valid {} for the transform {} in the file `{}`", name, i, path))
+                }
+            };
+            let size = |name:&str| -> Result<usize, String> {
+                match pixels(name)? {
+                    0 => Err(format!("dont find a valid {} for the transform {} in the file `{}`", name, i, path)),
+                    data => Ok(data)
+                }
+            };
+            let value = t["value"].as_f64().unwrap_or(0.0);
+            let interpolation = if let Some(interpolation) = parse_interpolation(&t["interpolation"]) {
+                interpolation
+            } else {
+                return Err(format!("doesn't find a valid interpolation for the transform {} in the file `{}`", i, path));
+            };
+
+            transforms.push(match t["transform"].as_str() {
+                Some("crop") => Transform::Crop(pixels("x")?, pixels("y")?, size("width")?, size("height")?),
+                Some("pad") => Transform::Pad(pixels("left")?, pixels("top")?, pixels("right")?, pixels("bottom")?, value),
+                Some("resample") => Transform::Resample(size("width")?, size("height")?, interpolation),
+                Some("resolution") => Transform::Resolution(positive("pixel size")?, interpolation),
+                Some("rotate") => Transform::Rotate(param("angle")?, value, interpolation),
+                Some("mirror") if t["axis"] == "x" => Transform::MirrorX,
+                Some("mirror") if t["axis"] == "y" => Transform::MirrorY,
+                Some("fit") => Transform::Fit(positive("width")?, positive("height")?, value),
+                _ => return Err(format!("doesn't find a valid transform {} in the file `{}`", i, path))
+            });
+        }
+
+        let mut preprocessing = vec![];
+        for (i, op) in object["preprocessing"].members().enumerate() {
+            let param = |name:&str| -> Result<f64, String> {
+                if let Some(data) = op[name].as_f64() {
+                    Ok(data)
+                } else {
+                    Err(format!("dont find a valid {} for the preprocessing operation {} in the file `{}`", name, i, path))
+                }
+            };
+            let positive = |name:&str| -> Result<f64, String> {
+                match param(name)? {
+                    data if data > 0.0 => Ok(data),
+                    _ => Err(format!("dont find a valid {} for the preprocessing operation {} in the file `{}`", name, i, path))
+                }
+            };
+
+            preprocessing.push(match op["operation"].as_str() {
+                Some("invert") => Preprocess::Invert,
+                Some("gamma") => Preprocess::Gamma(positive("value")?),
+                Some("levels") => match (param("low")?, param("high")?) {
+                    (low, high) if low < high => Preprocess::Levels(low, high),
+                    _ => return Err(format!("dont find valid levels for the preprocessing operation {} in the file `{}`", i, path))
+                },
+                Some("gaussian blur") => Preprocess::GaussianBlur(positive("sigma")?),
+                Some("median blur") => match param("radius")? {
+                    radius if radius >= 0.0 && radius.fract() == 0.0 => Preprocess::MedianBlur(radius as usize),
+                    _ => return Err(format!("dont find a valid radius for the preprocessing operation {} in the file `{}`", i, path))
+                },
+                Some("threshold") => Preprocess::Threshold(param("level")?),
+                Some("curve") => {
+                    let mut points = vec![];
+                    for point in op["points"].members() {
+                        match (point[0].as_f64(), point[1].as_f64()) {
+                            (Some(x), Some(y)) => points.push((x, y)),
+                            _ => return Err(format!("dont find valid points for the preprocessing operation {} in the file `{}`", i, path))
+                        }
+                    }
+                    if points.is_empty() || points.windows(2).any(|w| w[0].0 > w[1].0) {
+                        return Err(format!("dont find valid points for the preprocessing operation {} in the file `{}`", i, path));
+                    }
+                    Preprocess::Curve(points)
+                },
+                _ => return Err(format!("doesn't find a valid preprocessing operation {} in the file `{}`", i, path))
+            });
+        }
+
+        let mut layers = vec![];
+        for (name, layer) in object["layers"].entries() {
+            let operation = match layer["operation"].as_str() {
+                Some("pocket") => Operation::Pocket,
+                Some("profile") => Operation::Profile,
+                Some("engrave") => Operation::Engrave,
+                _ => return Err(format!("doesn't find a valid operation for the layer `{}` in the file `{}`", name, path))
+            };
+            let depth = if let Some(depth) = layer["depth"].as_f64() {
+                depth
+            } else {
+                return Err(format!("doesn't find a valid depth for the layer `{}` in the file `{}`", name, path));
+            };
+            layers.push((name.to_string(), LayerOperation{operation, depth}));
+        }
+
+        let tabs = if object["tabs"].is_null() {None} else {
+            let t = &object["tabs"];
+            let param = |name:&str| -> Result<f64, String> {
+                match t[name].as_f64() {
+                    Some(data) if data >= 0.0 => Ok(data),
+                    _ => Err(format!("dont find a valid {} for the tabs in the file `{}`", name, path))
+                }
+            };
+
+            let placement = if let Some(count) = t["count"].as_usize() {
+                TabPlacement::Count(count)
+            } else if let Some(spacing) = t["spacing"].as_f64().filter(|d| *d > 0.0) {
+                TabPlacement::Spacing(spacing)
+            } else if t["positions"].is_array() {
+                let mut positions = vec![];
+                for point in t["positions"].members() {
+                    match (point[0].as_f64(), point[1].as_f64()) {
+                        (Some(x), Some(y)) => positions.push((x, y)),
+                        _ => return Err(format!("dont find valid positions for the tabs in the file `{}`", path))
+                    }
+                }
+                TabPlacement::Positions(positions)
+            } else {
+                return Err(format!("doesn't find a valid count, spacing or positions for the tabs in the file `{}`", path));
+            };
+
+            Some(Tabs{
+                placement,
+                width:param("width")?,
+                height:param("height")?,
+                min_radius:if t["min radius"].is_null() {0.0} else {param("min radius")?}
+            })
+        };
+
+        let entry = if object["entry"].is_null() {None} else {
+            let max_angle = match object["entry"]["max angle"].as_f64() {
+                Some(angle) if angle > 0.0 && angle <= std::f64::consts::FRAC_PI_2 => angle,
+                _ => return Err(format!("dont find a valid max angle for the entry in the file `{}`", path))
+            };
+            let helix_radius = if object["entry"]["helix radius"].is_null() {0.0} else {
+                match object["entry"]["helix radius"].as_f64() {
+                    Some(radius) if radius >= 0.0 => radius,
+                    _ => return Err(format!("dont find a valid helix radius for the entry in the file `{}`", path))
+                }
+            };
+            Some(Entry{max_angle, helix_radius})
+        };
+
+        let adaptive_feed = if object["adaptive feed"].is_null() {None} else {
+            let a = &object["adaptive feed"];
+            let find = |name:&str| match a[name].as_f64() {
+                Some(value) if value > 0.0 => Ok(value),
+                _ => Err(format!("dont find a valid {} for the adaptive feed in the file `{}`", name, path))
+            };
+            let (min_speed, max_speed) = (find("min speed")?, find("max speed")?);
+            if min_speed > max_speed {
+                return Err(format!("the min speed is above the max speed of the adaptive feed in the file `{}`", path));
+            }
+            let resolution = if a["resolution"].is_null() {tool_shape.get_rayon() / 8.0} else {find("resolution")?};
+            Some(AdaptiveFeed{reference_depth:find("reference depth")?, min_speed, max_speed, resolution})
+        };
+
+        let milling_direction = match object["milling direction"].as_str() {
+            None if object["milling direction"].is_null() => None,
+            Some("climb") => Some(MillingDirection::Climb),
+            Some("conventional") => Some(MillingDirection::Conventional),
+            _ => return Err(format!("doesn't find a valid milling direction in the file `{}`", path))
+        };
+
+        let spindle_speed = if object["spindle speed"].is_null() {None} else {
+            match object["spindle speed"].as_f64() {
+                Some(speed) if speed > 0.0 => Some(speed),
+                _ => return Err(format!("dont find a valid spindle speed in the file `{}`", path))
+            }
+        };
+
+        let post_processor = {
+            let p = &object["post processor"];
+            let dialect = match p["dialect"].as_str() {
+                None if p["dialect"].is_null() => crate::gcode::Dialect::Grbl,
+                Some(name) => match PostProcessor::dialect_from_name(name) {
+                    Some(dialect) => dialect,
+                    None => return Err(format!("doesn't find a valid post processor dialect in the file `{}`", path))
+                },
+                None => return Err(format!("doesn't find a valid post processor dialect in the file `{}`", path))
+            };
+            let mut post_processor = PostProcessor::new(dialect);
+
+            let invalid = |name:&str| format!("dont find a valid {} for the post processor in the file `{}`", name, path);
+            let flag = |name:&str, default:bool| -> Result<bool, String> {
+                if p[name].is_null() {Ok(default)} else {p[name].as_bool().ok_or_else(|| invalid(name))}
+            };
+            let template = |name:&str, default:&str| -> Result<String, String> {
+                if p[name].is_null() {Ok(default.to_string())} else {p[name].as_str().map(|s| s.to_string()).ok_or_else(|| invalid(name))}
+            };
+
+            if !p["precision"].is_null() {
+                post_processor.precision = p["precision"].as_usize().filter(|n| *n <= 9).ok_or_else(|| invalid("precision"))?;
+            }
+            if !p["tolerance"].is_null() {
+                post_processor.tolerance = p["tolerance"].as_f64().filter(|t| *t > 0.0).ok_or_else(|| invalid("tolerance"))?;
+            }
+            post_processor.line_numbers = flag("line numbers", post_processor.line_numbers)?;
+            post_processor.checksums = flag("checksums", post_processor.checksums)?;
+            post_processor.arcs = flag("arcs", post_processor.arcs)?;
+            post_processor.path_blending = flag("path blending", post_processor.path_blending)?;
+            post_processor.comments = match p["comments"].as_str() {
+                None if p["comments"].is_null() => post_processor.comments,
+                Some("parentheses") => CommentStyle::Parentheses,
+                Some("semicolon") => CommentStyle::Semicolon,
+                _ => return Err(invalid("comments"))
+            };
+            post_processor.header = template("header", &post_processor.header)?;
+            post_processor.footer = template("footer", &post_processor.footer)?;
+            post_processor
+        };
+
+        let machine = if object["machine"].is_null() {None} else {
+            let m = &object["machine"];
+            let invalid = |name:&str| format!("dont find a valid {} for the machine in the file `{}`", name, path);
+            let (mut min, mut max, mut max_feed) = ([0.0; 3], [0.0; 3], [0.0; 3]);
+
+            for (i, axis) in ["x", "y", "z"].iter().enumerate() {
+                match (m["travel"][*axis][0].as_f64(), m["travel"][*axis][1].as_f64()) {
+                    (Some(low), Some(high)) if low <= high => {
+                        min[i] = low;
+                        max[i] = high;
+                    },
+                    _ => return Err(invalid("travel"))
+                }
+                max_feed[i] = m["max feed"][*axis].as_f64().filter(|f| *f > 0.0).ok_or_else(|| invalid("max feed"))?;
+            }
+
+            let max_spindle_speed = m["max spindle speed"].as_f64().filter(|s| *s > 0.0).ok_or_else(|| invalid("max spindle speed"))?;
+
+            let mut work_offset = [0.0; 3];
+            if !m["work offset"].is_null() {
+                for (i, offset) in work_offset.iter_mut().enumerate() {
+                    *offset = m["work offset"][i].as_f64().ok_or_else(|| invalid("work offset"))?;
+                }
+            }
+
+            Some(MachineProfile{min, max, max_feed, max_spindle_speed, work_offset})
+        };
+
+        let placement = if object["placement"].is_null() {Placement::new()} else {
+            let p = &object["placement"];
+            let invalid = |name:&str| format!("dont find a valid {} for the placement in the file `{}`", name, path);
+            let flag = |name:&str| -> Result<bool, String> {
+                if p[name].is_null() {Ok(false)} else {p[name].as_bool().ok_or_else(|| invalid(name))}
+            };
+
+            let origin = match p["origin"].as_str() {
+                None if p["origin"].is_null() => Origin::TopLeft,
+                Some("top left") => Origin::TopLeft,
+                Some("top right") => Origin::TopRight,
+                Some("bottom left") => Origin::BottomLeft,
+                Some("bottom right") => Origin::BottomRight,
+                Some("center") => Origin::Center,
+                _ => return Err(invalid("origin"))
+            };
+            let z_reference = match p["z zero"].as_str() {
+                None if p["z zero"].is_null() => ZReference::Surface,
+                Some("surface") => ZReference::Surface,
+                Some("bed") => ZReference::Bed(
+                    p["stock thickness"].as_f64().filter(|t| *t > 0.0).ok_or_else(|| invalid("stock thickness"))?
+                ),
+                _ => return Err(invalid("z zero"))
+            };
+            let work_offset = if p["work offset"].is_null() {None} else {
+                Some(p["work offset"].as_usize().filter(|i| (1..=6).contains(i)).ok_or_else(|| invalid("work offset"))?)
+            };
+
+            Placement{origin, flip_x:flag("flip x")?, flip_y:flag("flip y")?, z_reference, work_offset}
+        };
 
         Ok(Config{
             tool_shape,
+            machine,
+            placement,
+            tabs,
+            entry,
+            adaptive_feed,
+            milling_direction,
+            spindle_speed,
+            post_processor,
+            transforms,
+            preprocessing,
+            layers,
             vectical_speed:find_f64("vertical speed")?,
             horizontal_fly_speed:find_f64("horizontal fly speed")?,
             horizontal_work_speed:find_f64("horizontal work speed")?,
             fly_z:find_f64("flight height")?,
             normalizing,
+            normalizing_clip,
             depth:find_f64("depth")?,
             width:find_f64("width")?,
             height:find_f64("height")?
@@ -211,5 +793,71 @@ impl Config {
 
     }
 
+    /// return the operation associated to a layer, if any
+    pub fn layer_operation(&self, layer:&str) -> Option<&LayerOperation> {
+        self.layers.iter().find(|(name, _)| name == layer).map(|(_, op)| op)
+    }
+}
+
+/// return the configuration of the tests, the keys of the JSON object `extra` are added
+/// to a minimal configuration or replace its keys
+#[cfg(test)]
+pub fn test_config(extra:&str) -> Result<Config, String> {
+    let mut object = json::parse(r#"{
+        "tool shape" : {"shape" : "flat", "rayon" : 1e-3},
+        "flight height" : 2e-3,
+        "vertical speed" : 1e-3,
+        "horizontal work speed" : 1e-2,
+        "horizontal fly speed" : 1e-1,
+        "depth" : 1e-3,
+        "width" : 1e-1,
+        "height" : 1e-1,
+        "normalizing" : false
+    }"#).unwrap();
+    for (key, value) in json::parse(extra).unwrap().entries() {
+        object[key] = value.clone();
+    }
+    Config::new_from_json_obj(object, "test")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_config::*;
+
+    #[test]
+    fn test_invalid_preprocessing() {
+        let parse = |op:&str| test_config(&format!(r#"{{"preprocessing" : [{}]}}"#, op)).map(|c| c.preprocessing);
+
+        assert_eq!(parse(r#"{"operation" : "gamma", "value" : 2.0}"#), Ok(vec![Preprocess::Gamma(2.0)]));
+        assert!(parse(r#"{"operation" : "gamma", "value" : 0.0}"#).is_err());
+        assert!(parse(r#"{"operation" : "levels", "low" : 0.5, "high" : 0.5}"#).is_err());
+        assert!(parse(r#"{"operation" : "gaussian blur", "sigma" : -1.0}"#).is_err());
+        assert_eq!(parse(r#"{"operation" : "median blur", "radius" : 2}"#), Ok(vec![Preprocess::MedianBlur(2)]));
+        assert!(parse(r#"{"operation" : "median blur", "radius" : -1}"#).is_err());
+        assert!(parse(r#"{"operation" : "median blur", "radius" : 2.7}"#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_transforms() {
+        let parse = |t:&str| test_config(&format!(r#"{{"transforms" : [{}]}}"#, t)).map(|c| c.transforms);
 
+        assert_eq!(parse(r#"{"transform" : "crop", "x" : 0, "y" : 0, "width" : 2, "height" : 3}"#), Ok(vec![Transform::Crop(0, 0, 2, 3)]));
+        assert!(parse(r#"{"transform" : "crop", "x" : 0, "y" : 0, "width" : 0, "height" : 3}"#).is_err());
+        assert!(parse(r#"{"transform" : "resample", "width" : 10, "height" : 0}"#).is_err());
+        assert!(parse(r#"{"transform" : "resolution", "pixel size" : 0.0}"#).is_err());
+        assert!(parse(r#"{"transform" : "fit", "width" : -1.0, "height" : 1.0}"#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_machine() {
+        let parse = |spindle:f64| test_config(&format!(r#"{{"machine" : {{
+            "travel" : {{"x" : [0, 0.4], "y" : [0, 0.2], "z" : [-0.05, 0]}},
+            "max feed" : {{"x" : 0.05, "y" : 0.05, "z" : 0.01}},
+            "max spindle speed" : {}
+        }}}}"#, spindle)).map(|c| c.machine.map(|m| m.max_spindle_speed));
+
+        assert_eq!(parse(24000.0), Ok(Some(24000.0)));
+        assert!(parse(0.0).is_err());
+        assert!(parse(-1.0).is_err());
+    }
 }
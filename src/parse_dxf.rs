@@ -0,0 +1,554 @@
+use std::fs::read_to_string;
+use std::f64::consts::PI;
+
+use crate::segment::*;
+use crate::parse_config::{Config, LayerOperation};
+
+/// a shape read from a DXF file, the coordinates are in `m`,
+/// with the y-axis pointing up as in the DXF coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct DxfShape {
+    /// name of the layer of the entity
+    pub layer : String,
+
+    /// the points of the flattened entity
+    pub points : Vec<Vec2>,
+
+    /// if true, the last point is linked to the first one
+    pub closed : bool
+}
+
+impl DxfShape {
+    /// return the shape as a polygon if it is closed
+    pub fn to_polygon(&self) -> Option<Polygon> {
+        if self.closed && self.points.len() >= 3 {
+            Some(Polygon::new(self.points.clone()))
+        } else {None}
+    }
+}
+
+/// the result of the import of a DXF file
+pub struct DxfImport {
+    pub shapes : Vec<DxfShape>
+}
+
+impl DxfImport {
+    /// return the names of the layers used by the shapes, in order of appearance
+    pub fn layers(&self) -> Vec<&str> {
+        let mut layers : Vec<&str> = vec![];
+        for shape in self.shapes.iter() {
+            if !layers.contains(&shape.layer.as_str()) {layers.push(&shape.layer);}
+        }
+        layers
+    }
+
+    /// return the shapes of a layer
+    pub fn shapes_on_layer<'a>(&'a self, layer:&'a str) -> impl Iterator<Item = &'a DxfShape> {
+        self.shapes.iter().filter(move |s| s.layer == layer)
+    }
+
+    /// return the closed shapes of a layer as polygons
+    pub fn polygons(&self, layer:&str) -> Vec<Polygon> {
+        self.shapes_on_layer(layer).filter_map(|s| s.to_polygon()).collect()
+    }
+
+    /// return the shapes of each layer mapped to an operation by the configuration,
+    /// the layers without operation are ignored
+    pub fn operations<'a>(&'a self, config:&'a Config) -> Vec<(&'a str, &'a LayerOperation, Vec<&'a DxfShape>)> {
+        self.layers().into_iter()
+            .filter_map(|layer| config.layer_operation(layer).map(|op| (layer, op)))
+            .map(|(layer, op)| (layer, op, self.shapes_on_layer(layer).collect()))
+            .collect()
+    }
+
+    /// join the open shapes of a same layer whose endpoints are closer
+    /// than `tolerance`, at both ends of the chains, a chain that ends on its first
+    /// point becomes closed: drawings often describe a contour as a set of
+    /// independent `LINE` and `ARC`
+    pub fn join(&mut self, tolerance:f64) -> &mut Self {
+        let close = |a:Vec2, b:Vec2| {let d = a - b; d * d <= tolerance * tolerance};
+
+        let (mut open, closed) : (Vec<DxfShape>, Vec<DxfShape>) =
+            std::mem::take(&mut self.shapes).into_iter().partition(|s| !s.closed);
+        self.shapes = closed;
+
+        while let Some(mut chain) = open.pop() {
+            loop {
+                let first = chain.points[0];
+                let last = *chain.points.last().unwrap();
+
+                if chain.points.len() > 2 && close(first, last) {
+                    chain.points.pop();
+                    chain.closed = true;
+                    break;
+                }
+
+                let next = open.iter().position(|s| s.layer == chain.layer && (
+                    close(last, s.points[0]) || close(last, *s.points.last().unwrap())
+                ));
+
+                if let Some(i) = next {
+                    let mut other = open.swap_remove(i).points;
+                    if !close(last, other[0]) {other.reverse();}
+                    chain.points.extend(other.into_iter().skip(1));
+                    continue;
+                }
+
+                let previous = open.iter().position(|s| s.layer == chain.layer && (
+                    close(first, s.points[0]) || close(first, *s.points.last().unwrap())
+                ));
+
+                match previous {
+                    Some(i) => {
+                        let mut other = open.swap_remove(i).points;
+                        if !close(first, *other.last().unwrap()) {other.reverse();}
+                        other.pop();
+                        other.append(&mut chain.points);
+                        chain.points = other;
+                    },
+                    None => break
+                }
+            }
+
+            self.shapes.push(chain);
+        }
+
+        self
+    }
+}
+
+/// push the points of the arc of center `center`, radius `r` from the angle `a0`
+/// and of angle `sweep` (counter-clockwise if positive), except the first point
+fn flatten_arc(center:Vec2, r:f64, a0:f64, sweep:f64, tolerance:f64, out:&mut Vec<Vec2>) {
+    let step = if tolerance < r {2.0 * f64::acos(1.0 - tolerance / r)} else {PI / 2.0};
+    let n = usize::max(1, (sweep.abs() / step).ceil() as usize);
+
+    for i in 1..=n {
+        let a = a0 + sweep * (i as f64) / (n as f64);
+        out.push(center + Vec2::new(r * a.cos(), r * a.sin()));
+    }
+}
+
+/// push the points of a polyline segment from `p0` to `p1` with a bulge `bulge`
+/// (the tangent of a quarter of the included angle), except the first point
+fn flatten_bulge(p0:Vec2, p1:Vec2, bulge:f64, tolerance:f64, out:&mut Vec<Vec2>) {
+    let d = p1 - p0;
+    let chord = f64::sqrt(d * d);
+    if bulge.abs() < 1e-12 || chord < 1e-12 {
+        out.push(p1);
+        return;
+    }
+
+    let theta = 4.0 * bulge.atan();
+    let r = chord / (2.0 * f64::sin(theta / 2.0)).abs();
+    let left = Vec2::new(-d.get_y(), d.get_x()) * (1.0 / chord);
+    let center = (p0 + p1) * 0.5 + left * (chord / 2.0 / f64::tan(theta / 2.0));
+
+    let a0 = f64::atan2(p0.get_y() - center.get_y(), p0.get_x() - center.get_x());
+    flatten_arc(center, r, a0, theta, tolerance, out);
+    out.pop();
+    out.push(p1);
+}
+
+/// a (possibly rational) B-spline
+struct Spline {
+    degree: usize,
+    knots: Vec<f64>,
+    control: Vec<Vec2>,
+    weights: Vec<f64>
+}
+
+impl Spline {
+    /// evaluate the spline at the parameter `t` with the algorithm of de Boor
+    fn eval(&self, t:f64) -> Vec2 {
+        let p = self.degree;
+        let n = self.control.len();
+
+        let mut k = p;
+        while k + 1 < n && self.knots[k+1] <= t {k += 1;}
+
+        // homogeneous coordinates
+        let mut d : Vec<(Vec2, f64)> = (0..=p).map(|j| {
+            let w = self.weights[j + k - p];
+            (self.control[j + k - p] * w, w)
+        }).collect();
+
+        for r in 1..=p {
+            for j in (r..=p).rev() {
+                let i = j + k - p;
+                let den = self.knots[i + p + 1 - r] - self.knots[i];
+                let alpha = if den.abs() < 1e-12 {0.0} else {(t - self.knots[i]) / den};
+                d[j] = (d[j-1].0 * (1.0 - alpha) + d[j].0 * alpha, d[j-1].1 * (1.0 - alpha) + d[j].1 * alpha);
+            }
+        }
+
+        d[p].0 * (1.0 / d[p].1)
+    }
+
+    /// push the points of the spline between the parameters `t0` and `t1`,
+    /// except the first one, subdivide until the middle point is close to the chord
+    fn flatten(&self, t0:f64, t1:f64, tolerance:f64, depth:usize, out:&mut Vec<Vec2>) {
+        let (p0, p1) = (self.eval(t0), self.eval(t1));
+        let tm = (t0 + t1) * 0.5;
+        let pm = self.eval(tm);
+
+        let d = p1 - p0;
+        let length = f64::sqrt(d * d);
+        let v = pm - p0;
+        let distance = if length < 1e-12 {f64::sqrt(v * v)}
+            else {f64::abs(v.get_x() * d.get_y() - v.get_y() * d.get_x()) / length};
+
+        if depth >= 12 || (depth >= 2 && distance <= tolerance) {
+            out.push(p1);
+        } else {
+            self.flatten(t0, tm, tolerance, depth+1, out);
+            self.flatten(tm, t1, tolerance, depth+1, out);
+        }
+    }
+}
+
+/// an entity of the DXF file, as a list of group codes and values
+struct Entity<'a> {
+    kind: &'a str,
+    groups: Vec<(i32, &'a str)>
+}
+
+impl<'a> Entity<'a> {
+    fn string(&self, code:i32) -> Option<&'a str> {
+        self.groups.iter().find(|(c, _)| *c == code).map(|(_, v)| *v)
+    }
+
+    fn f64(&self, code:i32) -> Result<f64, String> {
+        self.f64_or(code, None)
+    }
+
+    fn f64_or(&self, code:i32, default:Option<f64>) -> Result<f64, String> {
+        match (self.string(code), default) {
+            (Some(v), _) => v.parse().or(Err(format!("invalid group code {} in entity `{}`", code, self.kind))),
+            (None, Some(d)) => Ok(d),
+            (None, None) => Err(format!("missing group code {} in entity `{}`", code, self.kind))
+        }
+    }
+
+    fn flags(&self) -> Result<i32, String> {
+        Ok(self.f64_or(70, Some(0.0))? as i32)
+    }
+
+    fn layer(&self) -> String {
+        self.string(8).unwrap_or("0").to_string()
+    }
+
+    /// return all the values of a group code in order
+    fn all(&self, code:i32) -> Result<Vec<f64>, String> {
+        self.groups.iter().filter(|(c, _)| *c == code)
+            .map(|(_, v)| v.parse().or(Err(format!("invalid group code {} in entity `{}`", code, self.kind))))
+            .collect()
+    }
+}
+
+/// split the content of a DXF file into pairs of group code and value
+fn read_groups(content:&str) -> Result<Vec<(i32, &str)>, String> {
+    let mut lines = content.lines();
+    let mut groups = vec![];
+
+    while let Some(code) = lines.next() {
+        if code.trim().is_empty() {continue;}
+        let code : i32 = code.trim().parse().or(Err(format!("invalid group code `{}`", code.trim())))?;
+        let value = lines.next().ok_or("unexpected end of file".to_string())?;
+        groups.push((code, value.trim()));
+    }
+
+    Ok(groups)
+}
+
+/// return the size of a drawing unit in `m` from the `$INSUNITS` header variable
+fn unit_scale(groups:&[(i32, &str)]) -> f64 {
+    let pos = groups.iter().position(|g| *g == (9, "$INSUNITS"));
+    let units = pos.and_then(|i| groups.get(i+1)).and_then(|(_, v)| v.parse::<i32>().ok());
+
+    match units {
+        Some(1) => 0.0254,
+        Some(2) => 0.3048,
+        Some(5) => 1e-2,
+        Some(6) => 1.0,
+        Some(8) => 0.0254e-6,
+        Some(9) => 0.0254e-3,
+        Some(10) => 0.9144,
+        Some(13) => 1e-6,
+        Some(14) => 1e-1,
+        // millimetres, also used for unitless drawings
+        _ => 1e-3
+    }
+}
+
+/// flatten an entity into a shape in drawing units, the unsupported entities are ignored
+fn flatten_entity(entity:&Entity, vertices:&[Entity], tolerance:f64) -> Result<Option<DxfShape>, String> {
+    let point = |x:i32, y:i32| -> Result<Vec2, String> {
+        Ok(Vec2::new(entity.f64(x)?, entity.f64(y)?))
+    };
+
+    // vertices and bulges of a polyline
+    let polyline = |vertices:Vec<(Vec2, f64)>, closed:bool| -> DxfShape {
+        let mut points = vec![];
+        if let Some((p, _)) = vertices.first() {points.push(*p);}
+
+        let n = vertices.len();
+        let segments = if closed {n} else {n.saturating_sub(1)};
+        for i in 0..segments {
+            let (p0, bulge) = vertices[i];
+            let (p1, _) = vertices[(i+1) % n];
+            flatten_bulge(p0, p1, bulge, tolerance, &mut points);
+        }
+        if closed {points.pop();}
+
+        DxfShape{layer:entity.layer(), points, closed}
+    };
+
+    let mut shape = match entity.kind {
+        "LINE" => DxfShape{layer:entity.layer(), points:vec![point(10, 20)?, point(11, 21)?], closed:false},
+        "CIRCLE" => {
+            let (center, r) = (point(10, 20)?, entity.f64(40)?);
+            let mut points = vec![];
+            flatten_arc(center, r, 0.0, 2.0 * PI, tolerance, &mut points);
+            points.pop();
+            points.insert(0, center + Vec2::new(r, 0.0));
+            DxfShape{layer:entity.layer(), points, closed:true}
+        },
+        "ARC" => {
+            let (center, r) = (point(10, 20)?, entity.f64(40)?);
+            let a0 = entity.f64(50)?.to_radians();
+            let mut sweep = entity.f64(51)?.to_radians() - a0;
+            while sweep <= 0.0 {sweep += 2.0 * PI;}
+
+            let mut points = vec![center + Vec2::new(r * a0.cos(), r * a0.sin())];
+            flatten_arc(center, r, a0, sweep, tolerance, &mut points);
+            DxfShape{layer:entity.layer(), points, closed:false}
+        },
+        "LWPOLYLINE" => {
+            // the bulge (code 42) follows the coordinates of its vertex
+            let mut vertices : Vec<(Vec2, f64)> = vec![];
+            let mut x = None;
+            for (code, value) in entity.groups.iter() {
+                let parse = || value.parse::<f64>().or(Err("invalid vertex in entity `LWPOLYLINE`".to_string()));
+                match code {
+                    10 => x = Some(parse()?),
+                    20 => vertices.push((Vec2::new(x.take().ok_or("invalid vertex in entity `LWPOLYLINE`")?, parse()?), 0.0)),
+                    42 => if let Some(v) = vertices.last_mut() {v.1 = parse()?;},
+                    _ => {}
+                }
+            }
+            polyline(vertices, entity.flags()? & 1 != 0)
+        },
+        "POLYLINE" => {
+            let vertices = vertices.iter().map(|v| -> Result<(Vec2, f64), String> {
+                Ok((Vec2::new(v.f64(10)?, v.f64(20)?), v.f64_or(42, Some(0.0))?))
+            }).collect::<Result<Vec<_>, String>>()?;
+            polyline(vertices, entity.flags()? & 1 != 0)
+        },
+        "SPLINE" => {
+            let (xs, ys) = (entity.all(10)?, entity.all(20)?);
+            let degree = entity.f64_or(71, Some(3.0))? as usize;
+            let knots = entity.all(40)?;
+            let mut weights = entity.all(41)?;
+            if weights.len() != xs.len() {weights = vec![1.0; xs.len()];}
+
+            let closed = entity.flags()? & 1 != 0;
+
+            if xs.len() == ys.len() && xs.len() > degree && knots.len() == xs.len() + degree + 1 {
+                let spline = Spline{
+                    degree, knots,
+                    control: xs.iter().zip(ys.iter()).map(|(x, y)| Vec2::new(*x, *y)).collect(),
+                    weights
+                };
+
+                let mut points = vec![spline.eval(spline.knots[degree])];
+                for k in degree..xs.len() {
+                    let (t0, t1) = (spline.knots[k], spline.knots[k+1]);
+                    if t1 > t0 {spline.flatten(t0, t1, tolerance, 0, &mut points);}
+                }
+
+                let last = *points.last().unwrap() - points[0];
+                if closed && last * last <= tolerance * tolerance {points.pop();}
+                DxfShape{layer:entity.layer(), points, closed}
+            } else {
+                // no valid control points, use the fit points
+                let (fx, fy) = (entity.all(11)?, entity.all(21)?);
+                if fx.len() != fy.len() || fx.len() < 2 {
+                    return Err("invalid entity `SPLINE`".to_string());
+                }
+                let points = fx.iter().zip(fy.iter()).map(|(x, y)| Vec2::new(*x, *y)).collect();
+                DxfShape{layer:entity.layer(), points, closed}
+            }
+        },
+        _ => return Ok(None)
+    };
+
+    // the planar entities are in their object coordinate system, with the extrusion
+    // `(0, 0, -1)` its x-axis is the opposite of the x-axis of the drawing
+    let planar = matches!(entity.kind, "CIRCLE" | "ARC" | "LWPOLYLINE") ||
+        (entity.kind == "POLYLINE" && entity.flags()? & 8 == 0);
+    if planar && entity.f64_or(230, Some(1.0))? < 0.0 {
+        shape.points.iter_mut().for_each(|p| *p = Vec2::new(-p.get_x(), p.get_y()));
+    }
+
+    Ok(Some(shape))
+}
+
+/// import the shapes of the `ENTITIES` section of the DXF document `content`,
+/// the curves are flattened with a maximal deviation of `tolerance` in `m`
+pub fn parse_dxf_str(content:&str, tolerance:f64) -> Result<DxfImport, String> {
+    assert!(tolerance > 0.0);
+
+    let groups = read_groups(content)?;
+    let scale = unit_scale(&groups);
+    let tolerance = tolerance / scale;
+
+    let start = groups.windows(2).position(|w| w[0] == (0, "SECTION") && w[1] == (2, "ENTITIES"))
+        .ok_or("doesn't find the section `ENTITIES`".to_string())?;
+
+    // group the codes of the section by entity
+    let mut entities : Vec<Entity> = vec![];
+    for (code, value) in groups[start+2..].iter() {
+        if *code == 0 {
+            if *value == "ENDSEC" {break;}
+            entities.push(Entity{kind:value, groups:vec![]});
+        } else if let Some(entity) = entities.last_mut() {
+            entity.groups.push((*code, value));
+        }
+    }
+
+    let mut shapes = vec![];
+    let mut i = 0;
+    while i < entities.len() {
+        // the vertices of a `POLYLINE` are the next entities until `SEQEND`
+        let mut j = i + 1;
+        if entities[i].kind == "POLYLINE" {
+            while j < entities.len() && entities[j].kind == "VERTEX" {j += 1;}
+        }
+
+        if let Some(mut shape) = flatten_entity(&entities[i], &entities[i+1..j], tolerance)? {
+            shape.points.iter_mut().for_each(|p| *p = *p * scale);
+            shapes.push(shape);
+        }
+        i = j;
+    }
+
+    Ok(DxfImport{shapes})
+}
+
+/// import the shapes of the DXF file at `path`,
+/// the curves are flattened with a maximal deviation of `tolerance` in `m`
+pub fn parse_dxf(path:&str, tolerance:f64) -> Result<DxfImport, String> {
+    if let Ok(content) = read_to_string(path) {
+        parse_dxf_str(&content, tolerance)
+    } else {
+        Err(format!("unable to open the file `{}`", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_dxf::*;
+
+    fn dxf(entities:&[&str]) -> String {
+        let mut out = "0\nSECTION\n2\nHEADER\n9\n$INSUNITS\n70\n4\n0\nENDSEC\n0\nSECTION\n2\nENTITIES\n".to_string();
+        for e in entities {out.push_str(e);}
+        out.push_str("0\nENDSEC\n0\nEOF\n");
+        out
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let content = dxf(&[
+            "0\nLINE\n8\ncut\n10\n0\n20\n0\n11\n10\n21\n0\n",
+            "0\nLINE\n8\ncut\n10\n10\n20\n10\n11\n10\n21\n0\n",
+            "0\nLINE\n8\ncut\n10\n10\n20\n10\n11\n0\n21\n10\n",
+            "0\nLINE\n8\ncut\n10\n0\n20\n10\n11\n0\n21\n0\n",
+            "0\nLINE\n8\nengrave\n10\n0\n20\n0\n11\n5\n21\n5\n",
+        ]);
+
+        let mut import = parse_dxf_str(&content, 1e-6).unwrap();
+        assert_eq!(import.layers(), vec!["cut", "engrave"]);
+
+        import.join(1e-6);
+        let polygons = import.polygons("cut");
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].len(), 4);
+        assert!(polygons[0].points().iter().all(|p| p.get_x() <= 1e-2 + 1e-12 && p.get_y() <= 1e-2 + 1e-12));
+        assert!(import.polygons("engrave").is_empty());
+    }
+
+    #[test]
+    fn test_join_order() {
+        // an open contour whose first piece is in the middle, with reversed pieces
+        let content = dxf(&[
+            "0\nLINE\n8\n0\n10\n2\n20\n0\n11\n3\n21\n0\n",
+            "0\nLINE\n8\n0\n10\n1\n20\n0\n11\n0\n21\n0\n",
+            "0\nLINE\n8\n0\n10\n3\n20\n0\n11\n4\n21\n0\n",
+            "0\nLINE\n8\n0\n10\n1\n20\n0\n11\n2\n21\n0\n",
+        ]);
+
+        let mut import = parse_dxf_str(&content, 1e-6).unwrap();
+        import.join(1e-6);
+        assert_eq!(import.shapes.len(), 1);
+        assert!(!import.shapes[0].closed);
+        let mut xs : Vec<f64> = import.shapes[0].points.iter().map(|p| p.get_x() * 1e3).collect();
+        if xs[0] > xs[xs.len()-1] {xs.reverse();}
+        assert!(xs.iter().zip([0.0, 1.0, 2.0, 3.0, 4.0]).all(|(x, e)| f64::abs(x - e) < 1e-9));
+    }
+
+    #[test]
+    fn test_extrusion() {
+        // an arc from 0 to 90 degrees, mirrored by the extrusion (0, 0, -1)
+        let content = dxf(&[
+            "0\nARC\n8\n0\n10\n5\n20\n0\n40\n1\n50\n0\n51\n90\n230\n-1\n",
+        ]);
+        let import = parse_dxf_str(&content, 1e-6).unwrap();
+        let points = &import.shapes[0].points;
+        let (first, last) = (points[0] * 1e3, points[points.len()-1] * 1e3);
+        assert!(f64::abs(first.get_x() + 6.0) < 1e-9 && f64::abs(first.get_y()) < 1e-9);
+        assert!(f64::abs(last.get_x() + 5.0) < 1e-9 && f64::abs(last.get_y() - 1.0) < 1e-9);
+        assert!(points.iter().all(|p| p.get_x() <= -5e-3 + 1e-12 && p.get_y() >= -1e-12));
+    }
+
+    #[test]
+    fn test_bulge_and_circle() {
+        // a slot: two half circles of radius 5 linked by two lines
+        let content = dxf(&[
+            "0\nLWPOLYLINE\n8\n0\n90\n4\n70\n1\n10\n0\n20\n0\n10\n20\n20\n0\n42\n1\n10\n20\n20\n10\n10\n0\n20\n10\n42\n1\n",
+            "0\nCIRCLE\n8\n0\n10\n0\n20\n0\n40\n2\n",
+        ]);
+
+        let tolerance = 1e-6;
+        let import = parse_dxf_str(&content, tolerance).unwrap();
+        assert_eq!(import.shapes.len(), 2);
+        assert!(import.shapes.iter().all(|s| s.closed));
+
+        let xs = import.shapes[0].points.iter().map(|p| p.get_x());
+        let max = xs.clone().fold(f64::NEG_INFINITY, f64::max);
+        let min = xs.fold(f64::INFINITY, f64::min);
+        assert!(max <= 25e-3 + 1e-12 && max >= 25e-3 - tolerance);
+        assert!(min >= -5e-3 - 1e-12 && min <= -5e-3 + tolerance);
+
+        for p in import.shapes[1].points.iter() {
+            assert!(f64::abs(f64::sqrt(*p * *p) - 2e-3) < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_spline() {
+        // a quadratic B-spline with a clamped knot vector
+        let content = dxf(&[
+            "0\nSPLINE\n8\n0\n70\n8\n71\n2\n72\n6\n73\n3\n40\n0\n40\n0\n40\n0\n40\n1\n40\n1\n40\n1\n10\n0\n20\n0\n10\n1\n20\n2\n10\n2\n20\n0\n",
+        ]);
+
+        let import = parse_dxf_str(&content, 1e-7).unwrap();
+        let points = &import.shapes[0].points;
+        assert!(f64::abs(points[0].get_x()) < 1e-12);
+        assert!(f64::abs(points.last().unwrap().get_x() - 2e-3) < 1e-12);
+
+        // the top of the curve is at half the height of the control point
+        let top = points.iter().map(|p| p.get_y()).fold(f64::NEG_INFINITY, f64::max);
+        assert!(f64::abs(top - 1e-3) < 1e-7);
+    }
+}
@@ -3,7 +3,7 @@ use crate::height_map::*;
 
 // take a path to an image and return it as Rgb array
 pub fn parse_image(path : &str, background:Rgb<u8>) -> Result<HeightMap, String> {
-    let img = ImageReader::open("./test_png_image.png");//.unwrap().decode().unwrap();
+    let img = ImageReader::open(path);
 
     match img {
         Ok(img) => {
@@ -21,7 +21,7 @@ pub fn parse_image(path : &str, background:Rgb<u8>) -> Result<HeightMap, String>
                             hmap.set_from_rgba8(
                                 i as usize,
                                 j as usize,
-                                img.get_pixel(i, j).clone(),
+                                img.get_pixel(i, j),
                                 background
                             );
                         }
@@ -0,0 +1,675 @@
+use std::fs::read_to_string;
+use std::f64::consts::PI;
+
+use crate::segment::*;
+
+/// the result of the import of a SVG file, all the coordinates are in `m`,
+/// with the y-axis pointing down as in the SVG (and image) coordinates
+pub struct SvgImport {
+    /// the closed shapes of the file, usable as pocket boundaries or profile contours
+    pub polygons : Vec<Polygon>,
+
+    /// the open shapes of the file (lines, polylines, unclosed sub-paths)
+    pub polylines : Vec<Vec<Vec2>>
+}
+
+/// a 2D affine transformation `[a c e; b d f]` as defined by the SVG standard
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Matrix {a:f64, b:f64, c:f64, d:f64, e:f64, f:f64}
+
+impl Matrix {
+    fn identity() -> Self {
+        Matrix{a:1.0, b:0.0, c:0.0, d:1.0, e:0.0, f:0.0}
+    }
+
+    fn translate(x:f64, y:f64) -> Self {
+        Matrix{e:x, f:y, ..Self::identity()}
+    }
+
+    fn scale(x:f64, y:f64) -> Self {
+        Matrix{a:x, d:y, ..Self::identity()}
+    }
+
+    fn rotate(angle:f64) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Matrix{a:cos, b:sin, c:-sin, d:cos, e:0.0, f:0.0}
+    }
+
+    /// return the transformation `self` applied after `other`
+    fn then(&self, other:&Matrix) -> Matrix {
+        Matrix{
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f
+        }
+    }
+
+    fn apply(&self, v:Vec2) -> Vec2 {
+        Vec2::new(
+            self.a * v.get_x() + self.c * v.get_y() + self.e,
+            self.b * v.get_x() + self.d * v.get_y() + self.f
+        )
+    }
+
+    /// mean scaling factor of the transformation, used to convert
+    /// a tolerance in `m` to a tolerance in user units
+    fn mean_scale(&self) -> f64 {
+        f64::sqrt(f64::abs(self.a * self.d - self.b * self.c))
+    }
+
+    /// build a matrix from a transform function like `rotate(30, 10, 10)`
+    fn from_function(name:&str, args:&[f64]) -> Result<Matrix, &'static str> {
+        match (name, args.len()) {
+            ("matrix", 6) => Ok(Matrix{a:args[0], b:args[1], c:args[2], d:args[3], e:args[4], f:args[5]}),
+            ("translate", 1) => Ok(Self::translate(args[0], 0.0)),
+            ("translate", 2) => Ok(Self::translate(args[0], args[1])),
+            ("scale", 1) => Ok(Self::scale(args[0], args[0])),
+            ("scale", 2) => Ok(Self::scale(args[0], args[1])),
+            ("rotate", 1) => Ok(Self::rotate(args[0].to_radians())),
+            ("rotate", 3) => Ok(
+                Self::translate(args[1], args[2])
+                    .then(&Self::rotate(args[0].to_radians()))
+                    .then(&Self::translate(-args[1], -args[2]))
+            ),
+            ("skewX", 1) => Ok(Matrix{c:args[0].to_radians().tan(), ..Self::identity()}),
+            ("skewY", 1) => Ok(Matrix{b:args[0].to_radians().tan(), ..Self::identity()}),
+            _ => Err("valid transform function")
+        }
+    }
+}
+
+peg::parser!{
+    grammar svg_grammar() for str {
+        rule space() = [' ' | '\t' | '\n' | '\r']
+
+        rule sep() = space()* ","? space()*
+
+        rule number() -> f64
+            = n:$(['+' | '-']? (['0'..='9']+ ("." ['0'..='9']*)? / "." ['0'..='9']+) (['e' | 'E'] ['+' | '-']? ['0'..='9']+)?)
+            {? n.parse().or(Err("number")) }
+
+        rule flag() -> f64 = "0" {0.0} / "1" {1.0}
+
+        rule arc_args() -> Vec<f64>
+            = rx:number() sep() ry:number() sep() r:number() sep() large:flag() sep() sweep:flag() sep() x:number() sep() y:number()
+            { vec![rx, ry, r, large, sweep, x, y] }
+
+        rule command() -> (char, Vec<f64>)
+            = c:$(['M' | 'm' | 'L' | 'l' | 'H' | 'h' | 'V' | 'v' | 'C' | 'c' | 'S' | 's' | 'Q' | 'q' | 'T' | 't']) sep() args:(number() ** sep())
+            { (c.chars().next().unwrap(), args) }
+            / c:$(['A' | 'a']) sep() args:(arc_args() ** sep())
+            { (c.chars().next().unwrap(), args.concat()) }
+            / c:$(['Z' | 'z'])
+            { (c.chars().next().unwrap(), vec![]) }
+
+        /// parse the `d` attribute of a `path`
+        pub rule path_data() -> Vec<(char, Vec<f64>)>
+            = sep() c:(command() ** sep()) sep() ![_] { c }
+
+        /// parse the `points` attribute of a `polyline` or a `polygon`
+        pub rule numbers() -> Vec<f64>
+            = sep() n:(number() ** sep()) sep() ![_] { n }
+
+        /// parse a length with an optional unit, return the length in `m`
+        /// (a length without unit is in pixels, 96 pixels per inch)
+        pub rule length() -> f64
+            = space()* n:number() space()* u:$(['a'..='z']*) space()* ![_]
+            {? match u {
+                "" | "px" => Ok(n * 0.0254 / 96.0),
+                "mm" => Ok(n * 1e-3),
+                "cm" => Ok(n * 1e-2),
+                "in" => Ok(n * 0.0254),
+                "pt" => Ok(n * 0.0254 / 72.0),
+                "pc" => Ok(n * 0.0254 / 6.0),
+                _ => Err("length unit")
+            }}
+
+        rule transform() -> Matrix
+            = name:$(['a'..='z' | 'A'..='Z']+) space()* "(" sep() args:(number() ** sep()) sep() ")"
+            {? Matrix::from_function(name, &args) }
+
+        /// parse the `transform` attribute of an element
+        pub rule transforms() -> Matrix
+            = sep() t:(transform() ** sep()) sep() ![_]
+            { t.iter().fold(Matrix::identity(), |acc, m| acc.then(m)) }
+    }
+}
+
+/// an XML element, only its name and attributes are kept
+struct Element<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, String)>,
+    self_closing: bool
+}
+
+impl<'a> Element<'a> {
+    fn attribute(&self, name:&str) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn number(&self, name:&str) -> Result<f64, String> {
+        match self.attribute(name) {
+            None => Ok(0.0),
+            Some(value) => value.trim().parse().or(Err(format!(
+                "invalid attribute `{}` of element `{}`", name, self.name
+            )))
+        }
+    }
+}
+
+/// the tokens of the XML document that matter for the import
+enum Tag<'a> {
+    Open(Element<'a>),
+    Close
+}
+
+/// decode the predefined XML entities of an attribute value
+fn decode_entities(value:&str) -> String {
+    value.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// a minimal XML tokenizer: return the opening and closing tags of the
+/// document in order, the comments, declarations and texts are skipped
+fn tokenize(content:&str) -> Result<Vec<Tag<'_>>, String> {
+    let mut tags = vec![];
+    let mut rest = content;
+
+    let skip = |rest:&str, end:&str| -> Result<usize, String> {
+        rest.find(end).map(|i| i + end.len()).ok_or(format!("unterminated `{}`", end))
+    };
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+
+        if rest.starts_with("<!--") {
+            rest = &rest[skip(rest, "-->")?..];
+        } else if rest.starts_with("<![CDATA[") {
+            rest = &rest[skip(rest, "]]>")?..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip(rest, "?>")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip(rest, ">")?..];
+        } else if rest.starts_with("</") {
+            rest = &rest[skip(rest, ">")?..];
+            tags.push(Tag::Close);
+        } else {
+            rest = &rest[1..];
+            let name_end = rest.find(|c:char| c.is_whitespace() || c == '/' || c == '>')
+                .ok_or("unterminated tag".to_string())?;
+            let name = &rest[..name_end];
+            rest = &rest[name_end..];
+
+            let mut attributes = vec![];
+            loop {
+                rest = rest.trim_start();
+                if let Some(r) = rest.strip_prefix("/>") {
+                    rest = r;
+                    tags.push(Tag::Open(Element{name, attributes, self_closing:true}));
+                    break;
+                } else if let Some(r) = rest.strip_prefix('>') {
+                    rest = r;
+                    tags.push(Tag::Open(Element{name, attributes, self_closing:false}));
+                    break;
+                }
+
+                let eq = rest.find('=').ok_or(format!("invalid attribute in the tag `{}`", name))?;
+                let attr_name = rest[..eq].trim();
+                rest = rest[eq+1..].trim_start();
+
+                let quote = rest.chars().next().ok_or("unterminated tag".to_string())?;
+                if quote != '"' && quote != '\'' {
+                    return Err(format!("unquoted attribute `{}` in the tag `{}`", attr_name, name));
+                }
+                let value_end = rest[1..].find(quote).ok_or("unterminated attribute".to_string())?;
+                attributes.push((attr_name, decode_entities(&rest[1..value_end+1])));
+                rest = &rest[value_end+2..];
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+/// flatten a cubic Bézier curve by recursive subdivision, push all
+/// the points of the curve except the first one
+fn flatten_cubic(p0:Vec2, p1:Vec2, p2:Vec2, p3:Vec2, tolerance:f64, depth:usize, out:&mut Vec<Vec2>) {
+    let chord = p3 - p0;
+    let length = f64::sqrt(chord * chord);
+
+    // distance of the control points to the chord
+    let distance = |p:Vec2| -> f64 {
+        let v = p - p0;
+        if length < 1e-12 {f64::sqrt(v * v)}
+        else {f64::abs(v.get_x() * chord.get_y() - v.get_y() * chord.get_x()) / length}
+    };
+
+    if depth >= 16 || (distance(p1) <= tolerance && distance(p2) <= tolerance) {
+        out.push(p3);
+        return;
+    }
+
+    let p01 = (p0 + p1) * 0.5;
+    let p12 = (p1 + p2) * 0.5;
+    let p23 = (p2 + p3) * 0.5;
+    let p012 = (p01 + p12) * 0.5;
+    let p123 = (p12 + p23) * 0.5;
+    let mid = (p012 + p123) * 0.5;
+
+    flatten_cubic(p0, p01, p012, mid, tolerance, depth+1, out);
+    flatten_cubic(mid, p123, p23, p3, tolerance, depth+1, out);
+}
+
+/// flatten an elliptical arc given with the endpoint parameterization of the SVG
+/// standard, push all the points of the arc except the first one
+#[allow(clippy::too_many_arguments)]
+fn flatten_arc(p0:Vec2, rx:f64, ry:f64, phi:f64, large:bool, sweep:bool, p1:Vec2, tolerance:f64, out:&mut Vec<Vec2>) {
+    let (mut rx, mut ry) = (rx.abs(), ry.abs());
+    if rx < 1e-12 || ry < 1e-12 || p0 == p1 {
+        out.push(p1);
+        return;
+    }
+
+    let (sin, cos) = phi.sin_cos();
+    let half = (p0 - p1) * 0.5;
+    let x1 = cos * half.get_x() + sin * half.get_y();
+    let y1 = -sin * half.get_x() + cos * half.get_y();
+
+    // scale up the radii if there is no solution
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1.0 {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let num = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let den = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut coef = f64::sqrt(f64::max(num / den, 0.0));
+    if large == sweep {coef = -coef;}
+
+    let cx1 = coef * rx * y1 / ry;
+    let cy1 = -coef * ry * x1 / rx;
+    let mid = (p0 + p1) * 0.5;
+    let center = Vec2::new(cos * cx1 - sin * cy1, sin * cx1 + cos * cy1) + mid;
+
+    let angle = |ux:f64, uy:f64| f64::atan2(uy, ux);
+    let theta1 = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+    let mut delta = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry) - theta1;
+    if sweep && delta < 0.0 {delta += 2.0 * PI;}
+    if !sweep && delta > 0.0 {delta -= 2.0 * PI;}
+
+    let r = f64::max(rx, ry);
+    let step = if tolerance < r {2.0 * f64::acos(1.0 - tolerance / r)} else {PI / 2.0};
+    let n = usize::max(1, (delta.abs() / step).ceil() as usize);
+
+    for i in 1..n {
+        let t = theta1 + delta * (i as f64) / (n as f64);
+        let (ts, tc) = t.sin_cos();
+        out.push(center + Vec2::new(cos * rx * tc - sin * ry * ts, sin * rx * tc + cos * ry * ts));
+    }
+    out.push(p1);
+}
+
+/// accumulate the sub-paths of an element, in user units
+struct Builder {
+    polygons: Vec<Vec<Vec2>>,
+    polylines: Vec<Vec<Vec2>>,
+    current: Vec<Vec2>
+}
+
+impl Builder {
+    fn new() -> Self {
+        Builder{polygons:vec![], polylines:vec![], current:vec![]}
+    }
+
+    fn last(&self) -> Vec2 {
+        *self.current.last().unwrap_or(&Vec2::new(0.0, 0.0))
+    }
+
+    /// terminate the current sub-path, it is closed if `close` is true
+    /// or if its endpoints are equal
+    fn end(&mut self, close:bool, tolerance:f64) {
+        let mut points = std::mem::take(&mut self.current);
+        if points.len() < 2 {return;}
+
+        let gap = points[points.len()-1] - points[0];
+        let closed = f64::sqrt(gap * gap) <= tolerance;
+        if closed {points.pop();}
+
+        if close || closed {
+            if points.len() >= 3 {self.polygons.push(points);}
+        } else {
+            self.polylines.push(points);
+        }
+    }
+}
+
+/// flatten the `d` attribute of a path element into the builder
+fn flatten_path(data:&str, tolerance:f64, builder:&mut Builder) -> Result<(), String> {
+    let commands = svg_grammar::path_data(data)
+        .map_err(|e| format!("invalid path data at position {}", e.location.offset))?;
+
+    let mut start = Vec2::new(0.0, 0.0);
+    // last control point of the previous curve, used by the smooth curves
+    let mut last_cubic : Option<Vec2> = None;
+    let mut last_quad : Option<Vec2> = None;
+
+    for (c, args) in commands {
+        let upper = c.to_ascii_uppercase();
+        let size = match upper {
+            'M' | 'L' | 'T' => 2, 'H' | 'V' => 1, 'C' => 6, 'S' | 'Q' => 4, 'A' => 7, _ => 0
+        };
+
+        if size == 0 {
+            if !builder.current.is_empty() {builder.current.push(start);}
+            builder.end(true, tolerance);
+            builder.current.push(start);
+            last_cubic = None;
+            last_quad = None;
+            continue;
+        }
+
+        if args.is_empty() || args.len() % size != 0 {
+            return Err(format!("invalid number of arguments for the path command `{}`", c));
+        }
+
+        let relative = c.is_ascii_lowercase();
+
+        for (i, a) in args.chunks(size).enumerate() {
+            let current = builder.last();
+            let point = |x:f64, y:f64| -> Vec2 {
+                if relative {current + Vec2::new(x, y)} else {Vec2::new(x, y)}
+            };
+
+            let mut cubic = None;
+            let mut quad = None;
+
+            match upper {
+                'M' if i == 0 => {
+                    builder.end(false, tolerance);
+                    start = point(a[0], a[1]);
+                    builder.current.push(start);
+                },
+                'M' | 'L' => builder.current.push(point(a[0], a[1])),
+                'H' => builder.current.push(Vec2::new(
+                    if relative {current.get_x() + a[0]} else {a[0]}, current.get_y()
+                )),
+                'V' => builder.current.push(Vec2::new(
+                    current.get_x(), if relative {current.get_y() + a[0]} else {a[0]}
+                )),
+                'C' | 'S' => {
+                    let (p1, p2, p3) = if upper == 'C' {
+                        (point(a[0], a[1]), point(a[2], a[3]), point(a[4], a[5]))
+                    } else {
+                        let p1 = match last_cubic {Some(p) => current * 2.0 - p, None => current};
+                        (p1, point(a[0], a[1]), point(a[2], a[3]))
+                    };
+                    flatten_cubic(current, p1, p2, p3, tolerance, 0, &mut builder.current);
+                    cubic = Some(p2);
+                },
+                'Q' | 'T' => {
+                    let (q, p2) = if upper == 'Q' {
+                        (point(a[0], a[1]), point(a[2], a[3]))
+                    } else {
+                        let q = match last_quad {Some(p) => current * 2.0 - p, None => current};
+                        (q, point(a[0], a[1]))
+                    };
+                    // degree elevation of the quadratic curve
+                    let p1 = current + (q - current) * (2.0 / 3.0);
+                    let p2_ctrl = p2 + (q - p2) * (2.0 / 3.0);
+                    flatten_cubic(current, p1, p2_ctrl, p2, tolerance, 0, &mut builder.current);
+                    quad = Some(q);
+                },
+                'A' => {
+                    let target = point(a[5], a[6]);
+                    flatten_arc(current, a[0], a[1], a[2].to_radians(), a[3] != 0.0, a[4] != 0.0, target, tolerance, &mut builder.current);
+                },
+                _ => unreachable!()
+            }
+
+            last_cubic = cubic;
+            last_quad = quad;
+        }
+    }
+
+    builder.end(false, tolerance);
+    Ok(())
+}
+
+/// push the points of an ellipse of center `(cx, cy)` in the builder as a closed sub-path
+fn flatten_ellipse(cx:f64, cy:f64, rx:f64, ry:f64, tolerance:f64, builder:&mut Builder) {
+    if rx <= 0.0 || ry <= 0.0 {return;}
+
+    let start = Vec2::new(cx + rx, cy);
+    builder.current.push(start);
+    flatten_arc(start, rx, ry, 0.0, false, true, Vec2::new(cx - rx, cy), tolerance, &mut builder.current);
+    flatten_arc(Vec2::new(cx - rx, cy), rx, ry, 0.0, false, true, start, tolerance, &mut builder.current);
+    builder.end(true, tolerance);
+}
+
+/// flatten the element `elem` in the builder, in user units
+fn flatten_element(elem:&Element, tolerance:f64, builder:&mut Builder) -> Result<(), String> {
+    let points = |name:&str| -> Result<Vec<Vec2>, String> {
+        let numbers = svg_grammar::numbers(elem.attribute(name).unwrap_or(""))
+            .map_err(|_| format!("invalid attribute `{}` of element `{}`", name, elem.name))?;
+        Ok(numbers.chunks_exact(2).map(|p| Vec2::new(p[0], p[1])).collect())
+    };
+
+    match elem.name {
+        "path" => flatten_path(elem.attribute("d").unwrap_or(""), tolerance, builder)?,
+        "line" => {
+            builder.current.push(Vec2::new(elem.number("x1")?, elem.number("y1")?));
+            builder.current.push(Vec2::new(elem.number("x2")?, elem.number("y2")?));
+            builder.end(false, tolerance);
+        },
+        "polyline" => {
+            builder.current = points("points")?;
+            builder.end(false, tolerance);
+        },
+        "polygon" => {
+            builder.current = points("points")?;
+            builder.end(true, tolerance);
+        },
+        "rect" => {
+            let (x, y) = (elem.number("x")?, elem.number("y")?);
+            let (w, h) = (elem.number("width")?, elem.number("height")?);
+            if w > 0.0 && h > 0.0 {
+                builder.current = vec![
+                    Vec2::new(x, y), Vec2::new(x + w, y),
+                    Vec2::new(x + w, y + h), Vec2::new(x, y + h)
+                ];
+                builder.end(true, tolerance);
+            }
+        },
+        "circle" => {
+            let r = elem.number("r")?;
+            flatten_ellipse(elem.number("cx")?, elem.number("cy")?, r, r, tolerance, builder);
+        },
+        "ellipse" => flatten_ellipse(
+            elem.number("cx")?, elem.number("cy")?,
+            elem.number("rx")?, elem.number("ry")?,
+            tolerance, builder
+        ),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// return the transformation from the user units of the root `svg` element to `m`
+fn root_matrix(elem:&Element) -> Result<Matrix, String> {
+    let length = |name:&str| -> Result<Option<f64>, String> {
+        match elem.attribute(name) {
+            None => Ok(None),
+            Some(value) if value.trim().ends_with('%') => Ok(None),
+            Some(value) => svg_grammar::length(value).map(Some)
+                .map_err(|_| format!("invalid attribute `{}` of the svg element", name))
+        }
+    };
+
+    let px = 0.0254 / 96.0;
+    let (width, height) = (length("width")?, length("height")?);
+
+    if let Some(view_box) = elem.attribute("viewBox") {
+        let v = svg_grammar::numbers(view_box)
+            .map_err(|_| "invalid attribute `viewBox` of the svg element".to_string())?;
+        if v.len() != 4 || v[2] <= 0.0 || v[3] <= 0.0 {
+            return Err("invalid attribute `viewBox` of the svg element".to_string());
+        }
+
+        let sx = width.map(|w| w / v[2]);
+        let sy = height.map(|h| h / v[3]);
+        let (sx, sy) = match (sx, sy) {
+            (Some(sx), Some(sy)) => (sx, sy),
+            (Some(s), None) | (None, Some(s)) => (s, s),
+            (None, None) => (px, px)
+        };
+
+        Ok(Matrix::scale(sx, sy).then(&Matrix::translate(-v[0], -v[1])))
+    } else {
+        Ok(Matrix::scale(px, px))
+    }
+}
+
+/// elements whose children are not drawn directly
+const HIDDEN: [&str; 7] = ["defs", "clipPath", "mask", "symbol", "pattern", "marker", "metadata"];
+
+/// import the shapes of the SVG document `content`,
+/// the curves are flattened with a maximal deviation of `tolerance` in `m`
+pub fn parse_svg_str(content:&str, tolerance:f64) -> Result<SvgImport, String> {
+    assert!(tolerance > 0.0);
+
+    let mut import = SvgImport{polygons:vec![], polylines:vec![]};
+
+    // the transformation and visibility of the opened elements
+    let mut stack : Vec<(Matrix, bool)> = vec![(Matrix::identity(), true)];
+
+    for tag in tokenize(content)? {
+        let elem = match tag {
+            Tag::Close => {
+                if stack.len() > 1 {stack.pop();}
+                continue;
+            },
+            Tag::Open(elem) => elem
+        };
+
+        let (parent, visible) = *stack.last().unwrap();
+
+        let mut matrix = if elem.name == "svg" && stack.len() == 1 {
+            root_matrix(&elem)?
+        } else {parent};
+
+        if let Some(transform) = elem.attribute("transform") {
+            let local = svg_grammar::transforms(transform)
+                .map_err(|_| format!("invalid attribute `transform` of element `{}`", elem.name))?;
+            matrix = matrix.then(&local);
+        }
+
+        let visible = visible && !HIDDEN.contains(&elem.name)
+            && elem.attribute("display") != Some("none");
+
+        if visible {
+            let mut builder = Builder::new();
+            let scale = matrix.mean_scale();
+            if scale > 0.0 {
+                flatten_element(&elem, tolerance / scale, &mut builder)?;
+            }
+
+            let map = |points:Vec<Vec2>| -> Vec<Vec2> {
+                points.into_iter().map(|p| matrix.apply(p)).collect()
+            };
+
+            import.polygons.extend(builder.polygons.into_iter().map(|p| Polygon::new(map(p))));
+            import.polylines.extend(builder.polylines.into_iter().map(map));
+        }
+
+        if !elem.self_closing {
+            stack.push((matrix, visible));
+        }
+    }
+
+    Ok(import)
+}
+
+/// import the shapes of the SVG file at `path`,
+/// the curves are flattened with a maximal deviation of `tolerance` in `m`
+pub fn parse_svg(path:&str, tolerance:f64) -> Result<SvgImport, String> {
+    if let Ok(content) = read_to_string(path) {
+        parse_svg_str(&content, tolerance)
+    } else {
+        Err(format!("unable to open the file `{}`", path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_svg::*;
+
+    #[test]
+    fn test_path_commands() {
+        let svg = r#"<?xml version="1.0"?>
+            <!-- a square and an open line -->
+            <svg width="10mm" height="10mm" viewBox="0 0 10 10">
+                <path d="M1,1 h8 v8 H1 z m 0 -1 l2-0.5"/>
+            </svg>"#;
+
+        let import = parse_svg_str(svg, 1e-6).unwrap();
+
+        assert_eq!(import.polygons.len(), 1);
+        assert_eq!(import.polylines.len(), 1);
+
+        let expected = [(1.0, 1.0), (9.0, 1.0), (9.0, 9.0), (1.0, 9.0)];
+        for (p, (x, y)) in import.polygons[0].points().iter().zip(expected) {
+            assert!(f64::abs(p.get_x() - x * 1e-3) < 1e-12);
+            assert!(f64::abs(p.get_y() - y * 1e-3) < 1e-12);
+        }
+
+        let line = &import.polylines[0];
+        assert!(f64::abs(line[1].get_x() - 3e-3) < 1e-12);
+        assert!(f64::abs(line[1].get_y() + 0.5e-3) < 1e-12);
+    }
+
+    #[test]
+    fn test_curve_tolerance() {
+        let svg = r#"<svg width="100mm" height="100mm" viewBox="0 0 100 100">
+                <g transform="translate(50, 50)">
+                    <circle cx="0" cy="0" r="40"/>
+                    <path d="M-10 0 A10 10 0 0 1 10 0 Q 0 20 -10 0 Z"/>
+                </g>
+                <defs><rect width="10" height="10"/></defs>
+            </svg>"#;
+
+        let tolerance = 1e-5;
+        let import = parse_svg_str(svg, tolerance).unwrap();
+        assert_eq!(import.polygons.len(), 2);
+
+        // all the points of the circle are on it, and the chords are close to it
+        let center = Vec2::new(50e-3, 50e-3);
+        let circle = &import.polygons[0];
+        for s in circle.segments() {
+            let d = s.source() - center;
+            assert!(f64::abs(f64::sqrt(d * d) - 40e-3) < 1e-9);
+
+            let m = (s.source() + s.target()) * 0.5 - center;
+            assert!(40e-3 - f64::sqrt(m * m) <= tolerance * 1.01);
+        }
+
+        // the arc goes through the top of the circle of radius 10mm
+        let top = import.polygons[1].points().iter()
+            .map(|p| p.get_y()).fold(f64::INFINITY, f64::min);
+        assert!(f64::abs(top - 40e-3) < 1e-9);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(parse_svg_str(r#"<svg><path d="M 1 2 L 3"/></svg>"#, 1e-5).is_err());
+        assert!(parse_svg_str(r#"<svg><path d="M 1 2 L 3 4"#, 1e-5).is_err());
+        assert!(parse_svg("./this_file_does_not_exist.svg", 1e-5).is_err());
+    }
+}
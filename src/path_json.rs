@@ -0,0 +1,129 @@
+use std::fs::{read_to_string, write};
+
+use json::{array, object, JsonValue};
+
+use crate::bit_map::{Move, Path};
+
+/// version of the JSON format of the paths, increased when it changes
+pub const PATH_FORMAT_VERSION : usize = 1;
+
+/// return the number `value` exactly as written, `as_f64` of the `json` crate may
+/// round the last digit and the paths would not round-trip
+fn exact_f64(value:&JsonValue) -> Option<f64> {
+    match value {
+        JsonValue::Number(number) => {
+            let (positive, mantissa, exponent) = number.as_parts();
+            format!("{}{}e{}", if positive {""} else {"-"}, mantissa, exponent).parse().ok()
+        },
+        _ => None
+    }
+}
+
+impl Move {
+    /// return the move as a JSON object, like `{"move" : "xy", "x" : 1e-3, "y" : 2e-3}`,
+    /// the names of the moves are "xy", "fly xy", "z", "xyz" and "feed" (with a "speed")
+    pub fn to_json(&self) -> JsonValue {
+        match *self {
+            Move::XYmove(x, y) => object!{"move" : "xy", "x" : x, "y" : y},
+            Move::FXYmove(x, y) => object!{"move" : "fly xy", "x" : x, "y" : y},
+            Move::Zmove(z) => object!{"move" : "z", "z" : z},
+            Move::XYZmove(x, y, z) => object!{"move" : "xyz", "x" : x, "y" : y, "z" : z},
+            Move::Feed(speed) => object!{"move" : "feed", "speed" : speed}
+        }
+    }
+
+    /// read a move written by `to_json`, `None` if it is not valid
+    pub fn from_json(object:&JsonValue) -> Option<Self> {
+        let get = |name:&str| exact_f64(&object[name]);
+        match object["move"].as_str()? {
+            "xy" => Some(Move::XYmove(get("x")?, get("y")?)),
+            "fly xy" => Some(Move::FXYmove(get("x")?, get("y")?)),
+            "z" => Some(Move::Zmove(get("z")?)),
+            "xyz" => Some(Move::XYZmove(get("x")?, get("y")?, get("z")?)),
+            "feed" => Some(Move::Feed(get("speed")?)),
+            _ => None
+        }
+    }
+}
+
+impl Path {
+    /// return the path as a JSON object with its format "version", its initial position
+    /// "x init", "y init", "z init" in `m` and the list of its "moves"
+    pub fn to_json(&self) -> JsonValue {
+        let mut moves = array![];
+        for m in self.path.iter() {
+            moves.push(m.to_json()).expect("a JSON array accepts any value");
+        }
+        object!{
+            "version" : PATH_FORMAT_VERSION,
+            "x init" : self.x_init,
+            "y init" : self.y_init,
+            "z init" : self.z_init,
+            "moves" : moves
+        }
+    }
+
+    /// read a path written by `to_json`, `path` is the name of the source in the errors
+    pub fn from_json(object:&JsonValue, path:&str) -> Result<Self, String> {
+        match object["version"].as_usize() {
+            Some(PATH_FORMAT_VERSION) => {},
+            Some(version) => return Err(format!("unsupported version {} of the path in the file `{}`", version, path)),
+            None => return Err(format!("dont find a valid version of the path in the file `{}`", path))
+        }
+
+        let find_f64 = |name:&str| exact_f64(&object[name])
+            .ok_or_else(|| format!("dont find a valid {} in the file `{}`", name, path));
+
+        if !object["moves"].is_array() {
+            return Err(format!("dont find the moves in the file `{}`", path));
+        }
+        let moves = object["moves"].members().enumerate()
+            .map(|(i, m)| Move::from_json(m).ok_or_else(|| format!("doesn't find a valid move {} in the file `{}`", i, path)))
+            .collect::<Result<Vec<Move>, String>>()?;
+
+        Ok(Path{x_init:find_f64("x init")?, y_init:find_f64("y init")?, z_init:find_f64("z init")?, path:moves})
+    }
+
+    /// write the path in the JSON file `path`, indented to be read and compared
+    pub fn save(&self, path:&str) -> Result<(), String> {
+        write(path, self.to_json().pretty(1)).map_err(|_| format!("unable to write the file `{}`", path))
+    }
+
+    /// read a path from the JSON file `path`
+    pub fn load(path:&str) -> Result<Self, String> {
+        let content = read_to_string(path).map_err(|_| format!("unable to open the file `{}`", path))?;
+        let object = json::parse(&content).map_err(|_| format!("unable to parse the file `{}`", path))?;
+        Self::from_json(&object, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::path_json::*;
+    use rand::Rng;
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = rand::thread_rng();
+        let mut path = Path{x_init:0.0, y_init:-1e-3, z_init:2e-3, path:vec![]};
+        for _ in 0..1000 {
+            let (x, y, z) = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1e-2..1e-2));
+            path.path.push(match rng.gen_range(0..5) {
+                0 => Move::XYmove(x, y),
+                1 => Move::FXYmove(x, y),
+                2 => Move::Zmove(z),
+                3 => Move::Feed(x.abs()),
+                _ => Move::XYZmove(x, y, z)
+            });
+        }
+
+        let text = path.to_json().dump();
+        assert_eq!(Path::from_json(&json::parse(&text).unwrap(), "test").unwrap(), path);
+
+        let mut object = path.to_json();
+        object["moves"][3]["move"] = "arc".into();
+        assert!(Path::from_json(&object, "test").unwrap_err().contains("move 3"));
+        object["version"] = 2.into();
+        assert!(Path::from_json(&object, "test").unwrap_err().contains("version 2"));
+    }
+}
@@ -0,0 +1,165 @@
+use crate::segment::Vec2;
+
+/// relative error bound of the determinant computed with floats, from Shewchuk's
+/// "Adaptive Precision Floating-Point Arithmetic and Fast Robust Geometric Predicates"
+const ORIENT_ERROR_BOUND : f64 = (3.0 + 16.0 * f64::EPSILON / 2.0) * f64::EPSILON / 2.0;
+
+/// return `a + b` and the rounding error of the sum, exactly `a + b = sum + error`
+fn two_sum(a:f64, b:f64) -> (f64, f64) {
+    let sum = a + b;
+    let b_virtual = sum - a;
+    let a_virtual = sum - b_virtual;
+    (sum, (a - a_virtual) + (b - b_virtual))
+}
+
+/// return `a * b` and the rounding error of the product, exactly `a * b = product + error`
+fn two_product(a:f64, b:f64) -> (f64, f64) {
+    let product = a * b;
+    (product, a.mul_add(b, -product))
+}
+
+/// add `b` to the expansion `e`, a list of non-overlapping floats of increasing magnitude
+/// whose exact sum is a number, the result is an expansion without zero
+fn grow_expansion(e:&[f64], b:f64) -> Vec<f64> {
+    let mut out = Vec::with_capacity(e.len() + 1);
+    let mut q = b;
+    for component in e.iter() {
+        let (sum, error) = two_sum(q, *component);
+        if error != 0.0 {out.push(error);}
+        q = sum;
+    }
+    if q != 0.0 {out.push(q);}
+    out
+}
+
+/// return the determinant `(b - a) x (c - a)`, positive if `a`, `b`, `c` are counter-clockwise
+/// with the y-axis pointing up, negative if they are clockwise and zero if they are aligned,
+/// its sign is always exact (without overflow and underflow), its value is approximated
+pub fn orient2d(a:Vec2, b:Vec2, c:Vec2) -> f64 {
+    let left = (a.get_x() - c.get_x()) * (b.get_y() - c.get_y());
+    let right = (a.get_y() - c.get_y()) * (b.get_x() - c.get_x());
+    let det = left - right;
+
+    // the float determinant is enough if it is far from zero
+    let bound = ORIENT_ERROR_BOUND * (left.abs() + right.abs());
+    if det.abs() > bound {return det;}
+
+    // otherwise compute it exactly from the six products of the coordinates
+    let (ax, ay, bx, by, cx, cy) = (a.get_x(), a.get_y(), b.get_x(), b.get_y(), c.get_x(), c.get_y());
+    let mut expansion = vec![];
+    for (u, v) in [(ax, by), (-ax, cy), (-ay, bx), (ay, cx), (bx, cy), (-by, cx)] {
+        let (product, error) = two_product(u, v);
+        expansion = grow_expansion(&expansion, error);
+        expansion = grow_expansion(&expansion, product);
+    }
+
+    // the components don't overlap, their sum has the sign of the largest one
+    expansion.iter().sum()
+}
+
+/// return the sign of `orient2d(a, b, c)`: `1` counter-clockwise, `-1` clockwise, `0` aligned
+pub fn orientation(a:Vec2, b:Vec2, c:Vec2) -> i8 {
+    let det = orient2d(a, b, c);
+    if det > 0.0 {1} else if det < 0.0 {-1} else {0}
+}
+
+/// return `true` if `p` is on the segment `[a, b]`, knowing that the three points are aligned
+fn on_aligned_segment(a:Vec2, b:Vec2, p:Vec2) -> bool {
+    f64::min(a.get_x(), b.get_x()) <= p.get_x() && p.get_x() <= f64::max(a.get_x(), b.get_x()) &&
+    f64::min(a.get_y(), b.get_y()) <= p.get_y() && p.get_y() <= f64::max(a.get_y(), b.get_y())
+}
+
+/// return `true` if the segments `[a, b]` and `[c, d]` share at least a point, exactly,
+/// the segments touching by an endpoint intersect
+pub fn segments_intersect(a:Vec2, b:Vec2, c:Vec2, d:Vec2) -> bool {
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+
+    if o1 * o2 < 0 && o3 * o4 < 0 {return true;}
+
+    (o1 == 0 && on_aligned_segment(a, b, c)) ||
+    (o2 == 0 && on_aligned_segment(a, b, d)) ||
+    (o3 == 0 && on_aligned_segment(c, d, a)) ||
+    (o4 == 0 && on_aligned_segment(c, d, b))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::predicates::*;
+    use rand::Rng;
+
+    /// the exact sign of the determinant of points with integer coordinates
+    fn exact_sign(a:Vec2, b:Vec2, c:Vec2) -> i8 {
+        let i = |v:f64| v as i128;
+        let det = (i(a.get_x()) - i(c.get_x())) * (i(b.get_y()) - i(c.get_y()))
+            - (i(a.get_y()) - i(c.get_y())) * (i(b.get_x()) - i(c.get_x()));
+        det.signum() as i8
+    }
+
+    #[test]
+    fn test_orientation() {
+        let mut rng = rand::thread_rng();
+        let mut float_errors = 0;
+
+        for _ in 0..100000 {
+            // nearly aligned points with integer coordinates up to 2^52, where the float
+            // determinant often has the wrong sign
+            let scale = (1u64 << rng.gen_range(0..52)) as f64;
+            let mut coordinate = || (rng.gen_range(-1.0..1.0) * scale).round();
+            let (a, d) = (Vec2::new(coordinate(), coordinate()), Vec2::new(coordinate(), coordinate()));
+            let t = rng.gen_range(-2.0..2.0);
+            let jitter = Vec2::new(rng.gen_range(-2..=2) as f64, rng.gen_range(-2..=2) as f64);
+            let b = a + d;
+            let c = Vec2::new((a + d * t).get_x().round(), (a + d * t).get_y().round()) + jitter;
+
+            let sign = orientation(a, b, c);
+            assert_eq!(sign, exact_sign(a, b, c));
+
+            // the sign doesn't depend on the order of the points, up to the parity of the permutation
+            assert_eq!(orientation(b, c, a), sign);
+            assert_eq!(orientation(c, a, b), sign);
+            assert_eq!(orientation(b, a, c), -sign);
+
+            let naive = (b - a).cross(c - a);
+            if (naive > 0.0) != (sign > 0) || (naive < 0.0) != (sign < 0) {float_errors += 1;}
+        }
+
+        // the inputs are hard enough to fool the float determinant
+        assert!(float_errors > 0);
+
+        // repeated points are aligned
+        let p = Vec2::new(0.1, 0.7);
+        assert_eq!(orientation(p, p, Vec2::new(3.0, -1.0)), 0);
+        assert_eq!(orientation(p, p, p), 0);
+    }
+
+    #[test]
+    fn test_segments_intersect() {
+        let p = |x, y| Vec2::new(x, y);
+
+        // touching by an endpoint, by an endpoint inside, collinear and disjoint
+        assert!(segments_intersect(p(0.0, 0.0), p(1.0, 1.0), p(1.0, 1.0), p(2.0, 0.0)));
+        assert!(segments_intersect(p(0.0, 0.0), p(2.0, 2.0), p(1.0, 1.0), p(2.0, 0.0)));
+        assert!(segments_intersect(p(0.0, 0.0), p(2.0, 2.0), p(1.0, 1.0), p(3.0, 3.0)));
+        assert!(!segments_intersect(p(0.0, 0.0), p(1.0, 1.0), p(2.0, 2.0), p(3.0, 3.0)));
+
+        // a vertical segment starting at a point very close to a segment of inexact slope
+        // crosses it only if the point is below or on it
+        let (a, b) = (p(0.1, 0.1), p(0.7, 0.3));
+        for bits in [-2i64, -1, 0, 1, 2] {
+            let start = p(0.4, f64::from_bits((0.2f64.to_bits() as i64 + bits) as u64));
+            assert_eq!(segments_intersect(a, b, start, p(0.4, 5.0)), orientation(a, b, start) <= 0);
+        }
+
+        // the result is symmetric on random nearly degenerate inputs
+        let mut rng = rand::thread_rng();
+        for _ in 0..10000 {
+            let mut q = || p((rng.gen_range(0..4) as f64) * 0.1, (rng.gen_range(0..4) as f64) * 0.1);
+            let (a, b, c, d) = (q(), q(), q(), q());
+            let result = segments_intersect(a, b, c, d);
+            assert_eq!(result, segments_intersect(c, d, a, b));
+            assert_eq!(result, segments_intersect(b, a, d, c));
+            if a == c || a == d || b == c || b == d {assert!(result);}
+        }
+    }
+}
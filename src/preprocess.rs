@@ -0,0 +1,216 @@
+use crate::height_map::*;
+use crate::parse_config::Preprocess;
+
+// the values of a height map imported from an image are between `-1.0` (black)
+// and `0.0` (white), the operations of this module work on the level `value + 1.0`
+// in `[0, 1]` and keep the values in `[-1, 0]`
+
+impl HeightMap {
+    /// apply a function to the level of each pixel
+    fn map_levels<F>(&mut self, f:F) -> &mut Self
+        where
+            F: Fn(f64) -> f64
+    {
+        for i in 0..self.get_width() {
+            for j in 0..self.get_height() {
+                let level = f(self.unsafe_get(i, j) + 1.0).clamp(0.0, 1.0);
+                self.unsafe_set(i, j, level - 1.0);
+            }
+        }
+        self
+    }
+
+    /// swap the white and black pixels
+    pub fn invert(&mut self) -> &mut Self {
+        self.map_levels(|t| 1.0 - t)
+    }
+
+    /// apply a gamma correction `t -> t^gamma` to the levels
+    pub fn gamma(&mut self, gamma:f64) -> &mut Self {
+        assert!(gamma > 0.0);
+        self.map_levels(|t| t.max(0.0).powf(gamma))
+    }
+
+    /// remap the levels with the piecewise linear curve going through `points`,
+    /// a list of `(input, output)` levels sorted by input
+    pub fn curve(&mut self, points:&[(f64, f64)]) -> &mut Self {
+        assert!(!points.is_empty());
+        assert!(points.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        self.map_levels(|t| {
+            if t <= points[0].0 {return points[0].1;}
+            for w in points.windows(2) {
+                let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+                if t <= x1 {
+                    return if x1 > x0 {y0 + (t - x0) * (y1 - y0) / (x1 - x0)} else {y1};
+                }
+            }
+            points[points.len()-1].1
+        })
+    }
+
+    /// stretch the levels such that `low` becomes black and `high` white,
+    /// the levels outside of `[low, high]` are clamped
+    pub fn levels(&mut self, low:f64, high:f64) -> &mut Self {
+        assert!(low < high);
+        self.map_levels(|t| (t - low) / (high - low))
+    }
+
+    /// set the pixels with a level smaller than `level` to black, and the others to white
+    pub fn threshold(&mut self, level:f64) -> &mut Self {
+        self.map_levels(|t| if t < level {0.0} else {1.0})
+    }
+
+    /// apply a gaussian blur of standard deviation `sigma` in pixels,
+    /// the borders are extended with the value of the nearest pixel
+    pub fn gaussian_blur(&mut self, sigma:f64) -> &mut Self {
+        assert!(sigma > 0.0);
+        if self.get_width() == 0 || self.get_height() == 0 {return self;}
+
+        let radius = (3.0 * sigma).ceil() as isize;
+        let kernel : Vec<f64> = (-radius..=radius)
+            .map(|i| f64::exp(-((i * i) as f64) / (2.0 * sigma * sigma)))
+            .collect();
+        let total : f64 = kernel.iter().sum();
+        let kernel : Vec<f64> = kernel.into_iter().map(|k| k / total).collect();
+
+        // the gaussian kernel is separable, blur along x then along y
+        let blur_x = self.par_map_pixels(|hmap, x, y| {
+            (-radius..=radius).map(|i| kernel[(i + radius) as usize] * hmap.get_clamped(x + i, y)).sum()
+        });
+        *self = blur_x.par_map_pixels(|hmap, x, y| {
+            (-radius..=radius).map(|i| kernel[(i + radius) as usize] * hmap.get_clamped(x, y + i)).sum()
+        });
+
+        self
+    }
+
+    /// replace each pixel by the median of the square of size `2 * radius + 1`
+    /// around it, the borders are extended with the value of the nearest pixel
+    pub fn median_blur(&mut self, radius:usize) -> &mut Self {
+        if self.get_width() == 0 || self.get_height() == 0 {return self;}
+
+        let radius = radius as isize;
+        *self = self.par_map_pixels(|hmap, x, y| {
+            let mut window : Vec<f64> = (-radius..=radius)
+                .flat_map(|i| (-radius..=radius).map(move |j| (i, j)))
+                .map(|(i, j)| hmap.get_clamped(x + i, y + j))
+                .collect();
+            let mid = window.len() / 2;
+            *window.select_nth_unstable_by(mid, f64::total_cmp).1
+        });
+
+        self
+    }
+
+    /// rescale the map such that its deepest point is at `-1.0` and its highest at `0.0`,
+    /// the fraction `clip` of the deepest and of the highest pixels are ignored
+    /// (and clamped) to be robust to outliers, a flat map is left unchanged
+    pub fn normalize(&mut self, clip:f64) -> &mut Self {
+        assert!((0.0..0.5).contains(&clip));
+
+        let mut values : Vec<f64> = (0..self.get_width())
+            .flat_map(|i| (0..self.get_height()).map(move |j| (i, j)))
+            .map(|(i, j)| self.unsafe_get(i, j))
+            .collect();
+        if values.is_empty() {return self;}
+        values.sort_unstable_by(f64::total_cmp);
+
+        let last = (values.len() - 1) as f64;
+        let low = values[(clip * last).floor() as usize];
+        let high = values[((1.0 - clip) * last).ceil() as usize];
+        if high - low < 1e-12 {return self;}
+
+        for i in 0..self.get_width() {
+            for j in 0..self.get_height() {
+                let level = ((self.unsafe_get(i, j) - low) / (high - low)).clamp(0.0, 1.0);
+                self.unsafe_set(i, j, level - 1.0);
+            }
+        }
+
+        self
+    }
+
+    /// apply a list of preprocessing operations in order
+    pub fn preprocess(&mut self, operations:&[Preprocess]) -> &mut Self {
+        for op in operations {
+            match op {
+                Preprocess::Invert => self.invert(),
+                Preprocess::Gamma(gamma) => self.gamma(*gamma),
+                Preprocess::Curve(points) => self.curve(points),
+                Preprocess::Levels(low, high) => self.levels(*low, *high),
+                Preprocess::GaussianBlur(sigma) => self.gaussian_blur(*sigma),
+                Preprocess::MedianBlur(radius) => self.median_blur(*radius),
+                Preprocess::Threshold(level) => self.threshold(*level)
+            };
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::preprocess::*;
+
+    fn level(hmap:&HeightMap, x:usize, y:usize) -> f64 {
+        hmap.get(x, y) + 1.0
+    }
+
+    #[test]
+    fn test_levels_operations() {
+        let mut hmap = HeightMap::new_with_buffer(2, 2, vec![-1.0, -0.75, -0.5, 0.0]);
+
+        hmap.invert();
+        assert!(f64::abs(level(&hmap, 0, 1) - 0.75) < 1e-12);
+
+        hmap.levels(0.25, 0.75);
+        assert!(f64::abs(level(&hmap, 0, 0) - 1.0) < 1e-12);
+        assert!(f64::abs(level(&hmap, 0, 1) - 1.0) < 1e-12);
+        assert!(f64::abs(level(&hmap, 1, 0) - 0.5) < 1e-12);
+        assert!(f64::abs(level(&hmap, 1, 1) - 0.0) < 1e-12);
+
+        hmap.gamma(2.0);
+        assert!(f64::abs(level(&hmap, 1, 0) - 0.25) < 1e-12);
+
+        hmap.curve(&[(0.0, 0.0), (0.5, 1.0), (1.0, 0.0)]);
+        assert!(f64::abs(level(&hmap, 1, 0) - 0.5) < 1e-12);
+        assert!(f64::abs(level(&hmap, 0, 0) - 0.0) < 1e-12);
+
+        hmap.threshold(0.5);
+        assert!(f64::abs(level(&hmap, 1, 0) - 1.0) < 1e-12);
+        assert!(f64::abs(level(&hmap, 1, 1) - 0.0) < 1e-12);
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut hmap = HeightMap::new(10, 10);
+        for i in 0..10 {for j in 0..10 {hmap.set(i, j, -0.2 - 0.01 * j as f64);}}
+        // an outlier
+        hmap.set(0, 0, -1.0);
+
+        hmap.normalize(0.02);
+        assert!(f64::abs(hmap.get(0, 0) + 1.0) < 1e-12);
+        assert!(f64::abs(hmap.get(5, 0) - 0.0) < 1e-12);
+        assert!(f64::abs(hmap.get(5, 9) + 1.0) < 1e-12);
+        assert!(f64::abs(hmap.get(5, 3) + 1.0 / 3.0) < 1e-12);
+    }
+
+    #[test]
+    fn test_blur() {
+        let mut hmap = HeightMap::new(9, 9);
+        for i in 0..9 {for j in 0..9 {hmap.set(i, j, -0.5);}}
+        hmap.set(4, 4, -1.0);
+
+        // the median removes an isolated pixel
+        let mut median = HeightMap::new_with_buffer(9, 9, (0..81).map(|i| hmap.get(i / 9, i % 9)).collect());
+        median.median_blur(1);
+        assert!(f64::abs(median.get(4, 4) + 0.5) < 1e-12);
+
+        // the gaussian blur spreads it symmetrically and keeps a constant map constant
+        hmap.gaussian_blur(1.0);
+        assert!(hmap.get(4, 4) > -1.0 && hmap.get(4, 4) < -0.5);
+        assert!(f64::abs(hmap.get(3, 4) - hmap.get(5, 4)) < 1e-12);
+        assert!(f64::abs(hmap.get(4, 3) - hmap.get(3, 4)) < 1e-12);
+        assert!(f64::abs(hmap.get(0, 8) + 0.5) < 1e-9);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::bit_map::{BitMap, Move, Path, PathAlgo};
+use crate::coordinates::{CoordinateMap, Placement};
+use crate::parse_config::MillingDirection;
+use crate::segment::Vec2;
+
+/// the raster strategy: cut the `true` pixels of a bit map along lines parallel
+/// to the x-axis, every `step` rows of pixels, at the depth `-depth`
+pub struct Raster {
+    /// size in `m` of a pixel along the x-axis and the y-axis
+    pub pixel_size : (f64, f64),
+
+    /// depth in `m` of the cut, the minimum value of z is `-depth`
+    pub depth : f64,
+
+    /// height of the tool during the rapid moves
+    pub fly_z : f64,
+
+    /// number of rows of pixels between two lines
+    pub step : usize,
+
+    /// if set, all the lines are cut in the same direction (one-way) such that the
+    /// material of the next lines is on the side given by the milling direction,
+    /// otherwise the lines alternate (zig-zag)
+    pub direction : Option<MillingDirection>,
+
+    /// placement of the paths relative to the bit map, the heights are given from the top of the stock
+    pub placement : Placement
+}
+
+impl Raster {
+    pub fn new(pixel_size:(f64, f64), depth:f64, fly_z:f64, step:usize) -> Self {
+        Raster{pixel_size, depth, fly_z, step:usize::max(1, step), direction:None, placement:Placement::new()}
+    }
+
+    /// return the intervals `[x0, x1]` of consecutive `true` pixels of the row `y`
+    fn spans(bit_map:&BitMap, y:usize) -> Vec<(usize, usize)> {
+        let mut out = vec![];
+        let mut start = None;
+
+        for x in 0..bit_map.get_width() {
+            match (bit_map.get(x, y), start) {
+                (true, None) => start = Some(x),
+                (false, Some(x0)) => {
+                    out.push((x0, x - 1));
+                    start = None;
+                },
+                _ => {}
+            }
+        }
+        if let Some(x0) = start {out.push((x0, bit_map.get_width() - 1));}
+
+        out
+    }
+}
+
+impl PathAlgo for Raster {
+    fn from_bit_map(&self, bit_map:&BitMap, x_init:f64, y_init:f64, z_init:f64) -> Path {
+        let map = CoordinateMap::new(self.placement, self.pixel_size, (bit_map.get_width(), bit_map.get_height()));
+        let at = |x:usize, y:usize| {
+            let p = map.to_path(Vec2::new(x as f64, y as f64));
+            (p.get_x(), p.get_y())
+        };
+        let mut path = vec![Move::Zmove(self.fly_z)];
+
+        // the material of the next lines is on the left of a line cut toward the increasing
+        // columns if the rows go to its left in the coordinates of the paths
+        let origin = map.to_path(Vec2::new(0.0, 0.0));
+        let (along, across) = (map.to_path(Vec2::new(1.0, 0.0)) - origin, map.to_path(Vec2::new(0.0, 1.0)) - origin);
+        let left = along.cross(across) > 0.0;
+        let one_way = self.direction.map(|d| (d == MillingDirection::Conventional) == left);
+
+        // the end of the last line, if the tool is still in the material
+        let mut last : Option<(usize, usize)> = None;
+
+        for (i, y) in (0..bit_map.get_height()).step_by(self.step).enumerate() {
+            let forward = one_way.unwrap_or(i % 2 == 0);
+
+            let mut spans = Self::spans(bit_map, y);
+            if !forward {
+                spans.reverse();
+                spans.iter_mut().for_each(|s| *s = (s.1, s.0));
+            }
+
+            for (x0, x1) in spans {
+                // in zig-zag, link to the previous line without rising if the tool stays
+                // in the region cut
+                let linked = match last {
+                    Some((lx, ly)) if one_way.is_none() && lx == x0 => (ly..=y).all(|j| bit_map.get(x0, j)),
+                    _ => false
+                };
+
+                if linked {
+                    let (x, y) = at(x0, y);
+                    path.push(Move::XYmove(x, y));
+                } else {
+                    if last.is_some() {path.push(Move::Zmove(self.fly_z));}
+                    let (x, y) = at(x0, y);
+                    path.push(Move::FXYmove(x, y));
+                    path.push(Move::Zmove(-self.depth));
+                }
+                if x1 != x0 {
+                    let (x, y) = at(x1, y);
+                    path.push(Move::XYmove(x, y));
+                }
+                last = Some((x1, y));
+            }
+        }
+        if last.is_some() {path.push(Move::Zmove(self.fly_z));}
+
+        Path{x_init, y_init, z_init, path:map.map_heights(path)}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::raster::*;
+    use crate::coordinates::{Origin, ZReference};
+
+    /// return the direction along the x-axis of each cutting move parallel to the x-axis
+    fn directions(path:&Path) -> Vec<f64> {
+        let mut x = path.x_init;
+        let mut out = vec![];
+        for m in path.path.iter() {
+            match m {
+                Move::XYmove(nx, _) => {
+                    if *nx != x {out.push((nx - x).signum());}
+                    x = *nx;
+                },
+                Move::FXYmove(nx, _) => x = *nx,
+                _ => {}
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_raster() {
+        let mut bmap = BitMap::new(6, 4);
+        for x in 1..5 {
+            for y in 0..4 {
+                bmap.set(x, y, true);
+            }
+        }
+
+        // zig-zag, without rising between the lines
+        let mut raster = Raster::new((1.0, 1.0), 1.0, 1.0, 1);
+        let path = raster.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        assert_eq!(directions(&path), vec![1.0, -1.0, 1.0, -1.0]);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::FXYmove(_, _))).count(), 1);
+
+        // one-way, the material of the next lines is on the right for climb milling
+        raster.direction = Some(MillingDirection::Climb);
+        let path = raster.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        assert_eq!(directions(&path), vec![-1.0; 4]);
+        assert_eq!(path.path.iter().filter(|m| matches!(m, Move::FXYmove(_, _))).count(), 4);
+
+        raster.direction = Some(MillingDirection::Conventional);
+        raster.step = 2;
+        let path = raster.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        assert_eq!(directions(&path), vec![1.0; 2]);
+
+        // with the y-axis pointing up, the next lines are on the other side
+        raster.placement = Placement{origin:Origin::BottomLeft, flip_y:true, z_reference:ZReference::Bed(2.0), ..Placement::new()};
+        let path = raster.from_bit_map(&bmap, 0.0, 0.0, 0.0);
+        assert_eq!(directions(&path), vec![-1.0; 2]);
+        assert_eq!(path.path[0], Move::Zmove(3.0));
+        assert!(path.path.contains(&Move::FXYmove(4.0, 3.0)) && path.path.contains(&Move::Zmove(1.0)));
+    }
+}
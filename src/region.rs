@@ -0,0 +1,229 @@
+use crate::bit_map::BitMap;
+use crate::segment::Vec2;
+
+/// the neighbours of a pixel used to decide if two pixels are connected
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connectivity {
+    /// the pixels sharing an edge
+    Four,
+    /// the pixels sharing an edge or a corner
+    Eight
+}
+
+impl Connectivity {
+    /// return the offsets of the neighbours of a pixel
+    pub fn neighbours(&self) -> &'static [(isize, isize)] {
+        match self {
+            Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+            Connectivity::Eight => &[(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)]
+        }
+    }
+}
+
+/// a connected region of `true` pixels of a bit map
+#[derive(Clone, Debug, PartialEq)]
+pub struct Region {
+    /// the label of the region, starting from `1`
+    pub label : usize,
+
+    /// number of pixels of the region
+    pub area : usize,
+
+    /// smallest coordinates of the pixels of the region
+    pub min : (usize, usize),
+
+    /// largest coordinates of the pixels of the region
+    pub max : (usize, usize),
+
+    /// mean of the coordinates of the pixels of the region
+    pub centroid : Vec2
+}
+
+/// the connected regions of a bit map, each pixel is associated to
+/// the label of its region, or `0` if it is `false`
+pub struct Labelling {
+    width : usize,
+    height : usize,
+    labels : Vec<usize>,
+    regions : Vec<Region>
+}
+
+impl Labelling {
+    /// return the label of the pixel `(x, y)`, `0` if it is `false`
+    pub fn get(&self, x:usize, y:usize) -> usize {
+        assert!(x < self.width && y < self.height);
+        self.labels[x * self.height + y]
+    }
+
+    /// return the regions, sorted by label
+    pub fn regions(&self) -> &[Region] {&self.regions}
+
+    /// return the region of label `label`
+    pub fn region(&self, label:usize) -> &Region {
+        &self.regions[label - 1]
+    }
+
+    /// return a map of the same size as the labelled map where only
+    /// the pixels of the region `label` are `true`
+    pub fn extract(&self, label:usize) -> BitMap {
+        let mut out = BitMap::new(self.width, self.height);
+        let region = self.region(label);
+
+        for x in region.min.0..=region.max.0 {
+            for y in region.min.1..=region.max.1 {
+                if self.labels[x * self.height + y] == label {out.set(x, y, true);}
+            }
+        }
+
+        out
+    }
+
+    /// return a map of the bounding box of the region `label` where only
+    /// its pixels are `true`, the pixel `(0, 0)` of the map is the pixel `region.min`
+    pub fn extract_cropped(&self, label:usize) -> BitMap {
+        let region = self.region(label);
+        let mut out = BitMap::new(region.max.0 + 1 - region.min.0, region.max.1 + 1 - region.min.1);
+
+        for x in region.min.0..=region.max.0 {
+            for y in region.min.1..=region.max.1 {
+                if self.labels[x * self.height + y] == label {
+                    out.set(x - region.min.0, y - region.min.1, true);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+impl BitMap {
+    /// label the connected regions of `true` pixels, in the order of the
+    /// pixels `(0, 0), (0, 1), ..., (1, 0), ...`
+    pub fn label(&self, connectivity:Connectivity) -> Labelling {
+        let (width, height) = (self.get_width(), self.get_height());
+        let mut labels = vec![0; width * height];
+        let mut regions = vec![];
+        let mut stack = vec![];
+
+        for x in 0..width {
+            for y in 0..height {
+                if !self.unsafe_get(x, y) || labels[x * height + y] != 0 {continue;}
+
+                let label = regions.len() + 1;
+                let mut region = Region{label, area:0, min:(x, y), max:(x, y), centroid:Vec2::new(0.0, 0.0)};
+                let (mut sum_x, mut sum_y) = (0.0, 0.0);
+
+                labels[x * height + y] = label;
+                stack.push((x, y));
+
+                while let Some((i, j)) = stack.pop() {
+                    region.area += 1;
+                    region.min = (region.min.0.min(i), region.min.1.min(j));
+                    region.max = (region.max.0.max(i), region.max.1.max(j));
+                    sum_x += i as f64;
+                    sum_y += j as f64;
+
+                    for (dx, dy) in connectivity.neighbours() {
+                        let (ni, nj) = (i as isize + dx, j as isize + dy);
+                        if ni < 0 || nj < 0 || ni as usize >= width || nj as usize >= height {continue;}
+                        let (ni, nj) = (ni as usize, nj as usize);
+
+                        if self.unsafe_get(ni, nj) && labels[ni * height + nj] == 0 {
+                            labels[ni * height + nj] = label;
+                            stack.push((ni, nj));
+                        }
+                    }
+                }
+
+                region.centroid = Vec2::new(sum_x / region.area as f64, sum_y / region.area as f64);
+                regions.push(region);
+            }
+        }
+
+        Labelling{width, height, labels, regions}
+    }
+
+    /// return the map without the regions of less than `min_area` pixels,
+    /// use the area of the tool `PI * (tool.get_rayon() / pixel_size)^2`
+    /// to discard the specks the tool can't machine
+    pub fn remove_small_regions(&self, min_area:usize, connectivity:Connectivity) -> BitMap {
+        let labelling = self.label(connectivity);
+        let mut out = BitMap::new(self.get_width(), self.get_height());
+
+        for x in 0..self.get_width() {
+            for y in 0..self.get_height() {
+                let label = labelling.get(x, y);
+                if label != 0 && labelling.region(label).area >= min_area {
+                    out.set(x, y, true);
+                }
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::region::*;
+
+    fn from_rows(rows:&[&str]) -> BitMap {
+        let mut bmap = BitMap::new(rows[0].len(), rows.len());
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                bmap.set(x, y, c == '#');
+            }
+        }
+        bmap
+    }
+
+    #[test]
+    fn test_label() {
+        let bmap = from_rows(&[
+            "##...",
+            "##...",
+            "..#..",
+            "...#.",
+        ]);
+
+        let four = bmap.label(Connectivity::Four);
+        assert_eq!(four.regions().len(), 3);
+        assert_eq!(four.region(1).area, 4);
+        assert_eq!(four.region(1).max, (1, 1));
+        assert_eq!(four.region(1).centroid, Vec2::new(0.5, 0.5));
+        assert_eq!(four.get(2, 2), 2);
+        assert_eq!(four.get(3, 3), 3);
+        assert_eq!(four.get(4, 3), 0);
+
+        // the diagonal pixels are connected with the 8-connectivity
+        let eight = bmap.label(Connectivity::Eight);
+        assert_eq!(eight.regions().len(), 1);
+        assert_eq!(eight.region(1).area, 6);
+        assert_eq!(eight.region(1).min, (0, 0));
+        assert_eq!(eight.region(1).max, (3, 3));
+    }
+
+    #[test]
+    fn test_extract() {
+        let bmap = from_rows(&[
+            "#..##",
+            "#...#",
+            "#....",
+        ]);
+
+        let labelling = bmap.label(Connectivity::Four);
+        assert_eq!(labelling.regions().len(), 2);
+
+        let second = labelling.extract(2);
+        assert_eq!(second.count(), 3);
+        assert!(second.get(3, 0) && !second.get(0, 0));
+
+        let cropped = labelling.extract_cropped(2);
+        assert_eq!((cropped.get_width(), cropped.get_height()), (2, 2));
+        assert!(cropped.get(0, 0) && cropped.get(1, 1) && !cropped.get(0, 1));
+
+        let cleaned = bmap.remove_small_regions(3, Connectivity::Four);
+        assert_eq!(cleaned.count(), 6);
+        assert_eq!(bmap.remove_small_regions(4, Connectivity::Four).count(), 0);
+    }
+}
@@ -3,6 +3,8 @@ use std::ops::{Add, Mul, Sub, Neg};
 use rand::*;
 
 use crate::bit_map::BitMap;
+use crate::predicates::{orientation, segments_intersect};
+use crate::region::Connectivity;
 #[allow(unused_imports)]
 use crate::height_map::*;
 
@@ -26,6 +28,22 @@ impl Vec2 {
     pub fn get_y(&self) -> f64 {
         self.y
     }
+
+    /// return the z component of the cross product, positive if `other` is
+    /// counter-clockwise from `self` with the y-axis pointing up
+    pub fn cross(&self, other:Vec2) -> f64 {
+        self.x * other.y - self.y * other.x
+    }
+
+    /// return the euclidean norm of the vector
+    pub fn norm(&self) -> f64 {
+        f64::sqrt(self.x * self.x + self.y * self.y)
+    }
+
+    /// return the euclidean distance between two points
+    pub fn distance(&self, other:Vec2) -> f64 {
+        (*self - other).norm()
+    }
 }
 
 impl Add for Vec2 {
@@ -78,7 +96,7 @@ pub struct HalfLine {
 }
 
 /// represent a segment using a source and a target (the source and target need to be different)
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Segment {
     src:Vec2,
     tgt:Vec2
@@ -99,8 +117,256 @@ impl Segment {
     pub fn to_half_line(&self) -> HalfLine {
         HalfLine::new(self.src, self.tgt-self.src)
     }
+
+    /// return the length of the segment
+    pub fn length(&self) -> f64 {self.src.distance(self.tgt)}
+
+    /// return the point of the segment closest to `p`
+    pub fn closest_point(&self, p:Vec2) -> Vec2 {
+        let d = self.tgt - self.src;
+        let t = ((p - self.src) * d / (d * d)).clamp(0.0, 1.0);
+        self.src + d * t
+    }
+
+    /// return the distance between the segment and the point `p`
+    pub fn distance_to(&self, p:Vec2) -> f64 {
+        self.closest_point(p).distance(p)
+    }
+
+    /// return the intersection of two segments, including their endpoints, whether they
+    /// intersect and how is decided exactly, a crossing point is rounded, a touching
+    /// endpoint and the ends of an overlap are returned exactly
+    pub fn intersection(&self, other:&Segment) -> Intersection {
+        let (a, b, c, d) = (self.src, self.tgt, other.src, other.tgt);
+        if !segments_intersect(a, b, c, d) {return Intersection::None;}
+
+        let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+        let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+
+        if o1 == 0 && o2 == 0 {
+            // collinear segments, sorted along their main axis
+            let along = |p:Vec2| if (b.x - a.x).abs() >= (b.y - a.y).abs() {p.x} else {p.y};
+            let (a, b) = if along(a) <= along(b) {(a, b)} else {(b, a)};
+            let (c, d) = if along(c) <= along(d) {(c, d)} else {(d, c)};
+            let low = if along(a) >= along(c) {a} else {c};
+            let high = if along(b) <= along(d) {b} else {d};
+            return if low == high {Intersection::Point(low)} else {Intersection::Overlap(Segment::new(low, high))};
+        }
+
+        // an endpoint on the other segment
+        if o1 == 0 {return Intersection::Point(c);}
+        if o2 == 0 {return Intersection::Point(d);}
+        if o3 == 0 {return Intersection::Point(a);}
+        if o4 == 0 {return Intersection::Point(b);}
+
+        // a proper crossing
+        let (r, s) = (b - a, d - c);
+        let t = ((c - a).cross(s) / r.cross(s)).clamp(0.0, 1.0);
+        Intersection::Point(a + r * t)
+    }
+}
+
+/// the intersection of two segments
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Intersection {
+    None,
+    Point(Vec2),
+    /// the part shared by two collinear segments
+    Overlap(Segment)
+}
+
+/// an axis-aligned rectangle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min : Vec2,
+    pub max : Vec2
+}
+
+impl BoundingBox {
+    /// return the smallest box containing the points, `None` if there is no point
+    pub fn from_points(points:&[Vec2]) -> Option<Self> {
+        let first = *points.first()?;
+        Some(points.iter().fold(BoundingBox{min:first, max:first}, |b, p| BoundingBox{
+            min:Vec2::new(f64::min(b.min.x, p.x), f64::min(b.min.y, p.y)),
+            max:Vec2::new(f64::max(b.max.x, p.x), f64::max(b.max.y, p.y))
+        }))
+    }
+
+    pub fn width(&self) -> f64 {self.max.x - self.min.x}
+    pub fn height(&self) -> f64 {self.max.y - self.min.y}
+
+    /// return the smallest box containing the two boxes
+    pub fn union(&self, other:&BoundingBox) -> Self {
+        BoundingBox{
+            min:Vec2::new(f64::min(self.min.x, other.min.x), f64::min(self.min.y, other.min.y)),
+            max:Vec2::new(f64::max(self.max.x, other.max.x), f64::max(self.max.y, other.max.y))
+        }
+    }
+
+    /// return `true` if the point is in the box, borders included
+    pub fn contains(&self, p:Vec2) -> bool {
+        self.min.x <= p.x && p.x <= self.max.x && self.min.y <= p.y && p.y <= self.max.y
+    }
+
+    /// return `true` if the boxes share at least a point
+    pub fn intersects(&self, other:&BoundingBox) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x && self.min.y <= other.max.y && other.min.y <= self.max.y
+    }
+}
+
+/// an open polyline, represented by the list of its vertices
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polyline {
+    points:Vec<Vec2>
+}
+
+impl Polyline {
+    pub fn new(points:Vec<Vec2>) -> Self {
+        Polyline{points}
+    }
+
+    /// return the vertices of the polyline
+    pub fn points(&self) -> &[Vec2] {&self.points}
+
+    /// return the edges of the polyline
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        self.points.windows(2).map(|w| Segment::new(w[0], w[1]))
+    }
+
+    /// return the length of the polyline
+    pub fn length(&self) -> f64 {
+        self.segments().map(|s| s.length()).sum()
+    }
+
+    /// return the point at the arc length `s` from the first vertex, clamped to the polyline
+    pub fn point_at(&self, s:f64) -> Option<Vec2> {
+        let mut remaining = f64::max(0.0, s);
+        for segment in self.segments() {
+            let length = segment.length();
+            if remaining <= length && length > 0.0 {
+                return Some(segment.src + (segment.tgt - segment.src) * (remaining / length));
+            }
+            remaining -= length;
+        }
+        self.points.last().copied()
+    }
+
+    /// return the polyline with evenly spaced vertices at most `step` apart along the arc length,
+    /// including the first and the last vertex
+    pub fn resampled(&self, step:f64) -> Self {
+        assert!(step > 0.0);
+        let length = self.length();
+        let n = usize::max(1, (length / step).ceil() as usize);
+        Polyline::new((0..=n).filter_map(|k| self.point_at(length * k as f64 / n as f64)).collect())
+    }
+
+    /// return the point of the polyline closest to `p`
+    pub fn closest_point(&self, p:Vec2) -> Option<Vec2> {
+        closest(self.segments(), p).or(self.points.first().copied())
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::from_points(&self.points)
+    }
 }
 
+/// return the boundaries of a region (the points inside an odd number of them) oriented with
+/// the region on their left: the boundaries nested in an even number of other boundaries are
+/// counter-clockwise and the others clockwise (with the y-axis pointing up)
+pub fn orient_regions(polygons:&[Polygon]) -> Vec<Polygon> {
+    polygons.iter().enumerate().map(|(i, polygon)| {
+        let p = polygon.points()[0];
+        let depth = polygons.iter().enumerate().filter(|(j, other)| *j != i && other.contains(p)).count();
+        if polygon.is_counter_clockwise() == (depth % 2 == 0) {polygon.clone()} else {polygon.reversed()}
+    }).collect()
+}
+
+/// return the point of the segments closest to `p`
+fn closest(segments:impl Iterator<Item = Segment>, p:Vec2) -> Option<Vec2> {
+    segments.map(|s| s.closest_point(p)).min_by(|a, b| a.distance(p).total_cmp(&b.distance(p)))
+}
+
+
+/// a closed polygon, represented by the list of its vertices,
+/// the last vertex is implicitly linked to the first one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polygon {
+    points:Vec<Vec2>
+}
+
+impl Polygon {
+    /// create a polygon from the list of its vertices
+    pub fn new(points:Vec<Vec2>) -> Self {
+        Polygon{points}
+    }
+
+    /// return the vertices of the polygon
+    pub fn points(&self) -> &[Vec2] {&self.points}
+
+    /// return the number of vertices of the polygon
+    pub fn len(&self) -> usize {self.points.len()}
+
+    pub fn is_empty(&self) -> bool {self.points.is_empty()}
+
+    /// return the edges of the polygon, including the closing edge
+    /// from the last vertex to the first one
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        let n = self.points.len();
+        (0..n).map(move |i| Segment::new(self.points[i], self.points[(i+1) % n]))
+    }
+
+    /// return `true` if the point is inside the polygon (even-odd rule), decided exactly,
+    /// the points on the edges may be inside or outside
+    pub fn contains(&self, p:Vec2) -> bool {
+        let mut inside = false;
+        for s in self.segments() {
+            // the edge crosses the horizontal half line from `p` toward the increasing x
+            // if `p` is on the left of the edge oriented upward
+            if (s.src.y > p.y) != (s.tgt.y > p.y) {
+                let upward = if s.tgt.y > s.src.y {1} else {-1};
+                if orientation(s.src, s.tgt, p) * upward > 0 {inside = !inside;}
+            }
+        }
+        inside
+    }
+
+    /// return the polygon with its vertices in the reverse order
+    pub fn reversed(&self) -> Self {
+        Polygon::new(self.points.iter().rev().copied().collect())
+    }
+
+    /// return the area of the polygon, positive if its vertices are
+    /// counter-clockwise with the y-axis pointing up
+    pub fn signed_area(&self) -> f64 {
+        self.segments().map(|s| s.src.cross(s.tgt)).sum::<f64>() * 0.5
+    }
+
+    /// return `true` if the vertices are counter-clockwise with the y-axis pointing up
+    pub fn is_counter_clockwise(&self) -> bool {
+        self.signed_area() > 0.0
+    }
+
+    /// return the length of the boundary of the polygon
+    pub fn perimeter(&self) -> f64 {
+        self.segments().map(|s| s.length()).sum()
+    }
+
+    /// return the point of the boundary closest to `p`
+    pub fn closest_point(&self, p:Vec2) -> Option<Vec2> {
+        closest(self.segments(), p)
+    }
+
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        BoundingBox::from_points(&self.points)
+    }
+
+    /// return the boundary as a polyline starting and ending at the first vertex
+    pub fn to_polyline(&self) -> Polyline {
+        let mut points = self.points.clone();
+        if let Some(first) = self.points.first() {points.push(*first);}
+        Polyline::new(points)
+    }
+}
 
 impl HalfLine {
 
@@ -128,27 +394,21 @@ impl HalfLine {
     /// | self.dir.y  -line.dir.y | |Y|
     ///
     /// this fonction return the values of X, Y that verify this equation if
-    /// their exists
-    pub fn plan_intersection(&self, line:HalfLine, eps:f64) -> Option<Vec2> {
-        let equal_eps = |f1:f64, f2:f64| -> bool {
-            f1 + eps >= f2 && f2 + eps >= f1
-        };
-
+    /// their exists, the lines are parallel (without solution) only if their
+    /// directions are exactly aligned
+    pub fn plan_intersection(&self, line:HalfLine) -> Option<Vec2> {
         let b = line.src - self.src;
-        if equal_eps(b.x, 0.0) && equal_eps(b.y, 0.0) {
-            return Some(self.src);
+        if b.x == 0.0 && b.y == 0.0 {
+            return Some(Vec2::new(0.0, 0.0));
         }
 
-        let det = - self.dir.x * line.dir.y + line.dir.x * self.dir.y;
-
-        if equal_eps(det, 0.0) {return None;}
+        let zero = Vec2::new(0.0, 0.0);
+        if orientation(zero, self.dir, line.dir) == 0 {return None;}
 
+        let det = - self.dir.x * line.dir.y + line.dir.x * self.dir.y;
         let x = (-line.dir.y * b.x + line.dir.x * b.y) / det;
         let y = (-self.dir.y * b.x + self.dir.x * b.y) / det;
 
-        debug_assert!(equal_eps((self.src + self.dir * x).x, (line.src + line.dir * y).x));
-        debug_assert!(equal_eps((self.src + self.dir * x).y, (line.src + line.dir * y).y));
-
         Some(Vec2::new(x, y))
     }
 
@@ -163,56 +423,29 @@ pub enum Case2<T>{
 }
 
 
-pub fn get_segments_from_pixel<F>(f:F, x:isize, y:isize) -> Case2<Segment>
-    where
-        F: Fn(isize, isize) -> bool
-{
-    let p00 = f(x, y);
-    let p10 = f(x+1, y);
-    let p01 = f(x, y+1);
-    let p11 = f(x+1, y+1);
+/// return the segments of the cell whose corners are inside (`true`) or outside (`false`)
+/// of a region, `p00`, `p10`, `p01`, `p11` the corners `(x, y)`, `(x+1, y)`, `(x, y+1)`,
+/// `(x+1, y+1)`, with the region boundary crossing the bottom, left, right and top
+/// edges at `edges`; when only two opposite corners are inside (a saddle) they are
+/// linked through the cell if `join` and separated otherwise, the two segments never cross
+fn segments_from_cell(p00:bool, p10:bool, p01:bool, p11:bool, join:bool, edges:[Vec2; 4]) -> Case2<Segment> {
+    let [bottom, left, right, top] = edges;
 
-    let x = x as f64;
-    let y = y as f64;
-    let v00 = Vec2::new(x, y);
-    let v10 = Vec2::new(x+1.0, y);
-    let v01 = Vec2::new(x, y+1.0);
-    let v11 = Vec2::new(x+1.0, y+1.0);
-
-    let s11 = Segment::new(
-        (v10 + v11) * 0.5,
-        (v01 + v11) * 0.5
-    );
-
-    let s00 = Segment::new(
-        (v00 + v10) * 0.5,
-        (v00 + v01) * 0.5
-    );
-
-    let s10 = Segment::new(
-        (v11 + v10) * 0.5,
-        (v00 + v10) * 0.5
-    );
-
-    let s01 = Segment::new(
-        (v11 + v01) * 0.5,
-        (v00 + v01) * 0.5
-    );
+    let s11 = Segment::new(right, top);
+    let s00 = Segment::new(bottom, left);
+    let s10 = Segment::new(right, bottom);
+    let s01 = Segment::new(top, left);
 
     // TF
     // FT
     if p00 && p11 && !p10 && !p01 {
-        return Case2::C2(
-            s00, s11
-        );
+        return if join {Case2::C2(s10, s01)} else {Case2::C2(s00, s11)};
     }
 
     // FT
     // TF
     if !p00 && !p11 && p01 && p10 {
-        return Case2::C2(
-            s01, s11
-        );
+        return if join {Case2::C2(s00, s11)} else {Case2::C2(s10, s01)};
     }
 
     if p10 != p00 && p01 != p00 && p11 != p00 {return Case2::C1(s00);}
@@ -225,31 +458,99 @@ pub fn get_segments_from_pixel<F>(f:F, x:isize, y:isize) -> Case2<Segment>
 
     if p00 == p10 && p00 != p01 && p00 != p11 {
         return Case2::C1(
-            Segment::new(
-                (v00 + v01) * 0.5,
-                (v10 + v11) * 0.5
-            )
+            Segment::new(left, right)
         );
     }
 
     if p00 == p01 && p00 != p10 && p00 != p11 {
         return Case2::C1(
-            Segment::new(
-                (v00 + v10) * 0.5,
-                (v01 + v11) * 0.5
-            )
+            Segment::new(bottom, top)
         );
     }
 
     if p00 == p11 && p01 == p00 && p10 == p00 {return Case2::C0;}
 
     unreachable!()
+}
 
+/// return the middles of the bottom, left, right and top edges of the cell `(x, y)`
+fn edge_middles(x:isize, y:isize) -> [Vec2; 4] {
+    let (x, y) = (x as f64, y as f64);
+    [
+        Vec2::new(x + 0.5, y),
+        Vec2::new(x, y + 0.5),
+        Vec2::new(x + 1.0, y + 0.5),
+        Vec2::new(x + 0.5, y + 1.0)
+    ]
+}
+
+/// return `true` if the centre of a cell is lower or equal to `z`, its height is the
+/// average of the heights of the corners inside the map (`Some`)
+fn centre_below(heights:[Option<f64>; 4], z:f64) -> bool {
+    let known : Vec<f64> = heights.iter().flatten().copied().collect();
+    !known.is_empty() && known.iter().sum::<f64>() / known.len() as f64 <= z
+}
+
+/// return the boundary of the region of the pixels `(i, j)` such that `f(i, j)` in
+/// the cell between the pixels `(x, y)` and `(x+1, y+1)`, on the middle of the edges,
+/// the diagonal pixels of a saddle are in the same region with the 8-connectivity
+pub fn get_segments_from_pixel<F>(f:F, connectivity:Connectivity, x:isize, y:isize) -> Case2<Segment>
+    where
+        F: Fn(isize, isize) -> bool
+{
+    let join = connectivity == Connectivity::Eight;
+    segments_from_cell(f(x, y), f(x+1, y), f(x, y+1), f(x+1, y+1), join, edge_middles(x, y))
+}
+
+/// smallest distance in pixel between an interpolated point and the pixels of its edge,
+/// such that the points of two different edges are never equal
+const EDGE_MARGIN : f64 = 1e-9;
+
+/// return the point where the level `z` crosses the edge from the pixel `a` of height
+/// `ha` to the pixel `b` of height `hb`, interpolated linearly, in the middle of the edge
+/// if one of the pixels is outside of the map (`None`)
+fn edge_crossing(a:Vec2, ha:Option<f64>, b:Vec2, hb:Option<f64>, z:f64) -> Vec2 {
+    let t = match (ha, hb) {
+        (Some(ha), Some(hb)) if ha != hb => ((z - ha) / (hb - ha)).clamp(EDGE_MARGIN, 1.0 - EDGE_MARGIN),
+        _ => 0.5
+    };
+    a + (b - a) * t
+}
+
+/// like `get_segments_from_pixel` with the pixels of height `f(x, y)` inside the region
+/// if they are lower or equal to `z`, `None` outside of the map and of the region, the
+/// segments cross the edges where the heights interpolated linearly are `z` instead of
+/// their middle, each crossing depends only on its edge and two cells sharing an edge
+/// give exactly the same point; the saddles are resolved by the average height of the
+/// corners at the centre of the cell
+pub fn get_interpolated_segments_from_pixel<F>(f:F, z:f64, x:isize, y:isize) -> Case2<Segment>
+    where
+        F: Fn(isize, isize) -> Option<f64>
+{
+    let (h00, h10, h01, h11) = (f(x, y), f(x+1, y), f(x, y+1), f(x+1, y+1));
+    let inside = |h:Option<f64>| h.map(|h| h <= z).unwrap_or(false);
+
+    let x = x as f64;
+    let y = y as f64;
+    let v00 = Vec2::new(x, y);
+    let v10 = Vec2::new(x+1.0, y);
+    let v01 = Vec2::new(x, y+1.0);
+    let v11 = Vec2::new(x+1.0, y+1.0);
+
+    let join = centre_below([h00, h10, h01, h11], z);
+    segments_from_cell(inside(h00), inside(h10), inside(h01), inside(h11), join, [
+        edge_crossing(v00, h00, v10, h10, z),
+        edge_crossing(v00, h00, v01, h01, z),
+        edge_crossing(v10, h10, v11, h11, z),
+        edge_crossing(v01, h01, v11, h11, z)
+    ])
 }
 
 impl BitMap {
-    pub fn from_pixel_to_segments(&self, x:isize, y:isize) -> Case2<Segment> {
-        get_segments_from_pixel(|i:isize, j:isize | if i >= 0 && j >= 0 {self.get_default(i as usize, j as usize)} else {false}, x, y)
+    /// return the boundary of the `true` pixels between the pixel `(x, y)` and `(x+1, y+1)`,
+    /// the diagonal `true` pixels are linked with the `connectivity` of the regions
+    pub fn from_pixel_to_segments(&self, x:isize, y:isize, connectivity:Connectivity) -> Case2<Segment> {
+        get_segments_from_pixel(|i:isize, j:isize | if i >= 0 && j >= 0 {self.get_default(i as usize, j as usize)} else {false}, connectivity, x, y)
     }
 }
 
@@ -257,37 +558,32 @@ impl HeightMap {
     /// take a height map as input and return the set of segments
     /// (the boundary between pixels smaller and larger than z)
     /// include in this height map between the pixel
-    /// `(i, y)` and `(i+1, y+1)`
+    /// `(i, y)` and `(i+1, y+1)`, the saddles are resolved by the
+    /// average height of the corners at the centre of the cell
     pub fn from_pixel_to_segments(&self, x:isize, y:isize, z:f64) -> Case2<Segment> {
-        get_segments_from_pixel(|i:isize , j:isize | if i >= 0 && j >= 0 {self.get_default(i as usize, j as usize) <= z} else {false}, x, y)
-    }
-
-    pub fn get_f64(&self, x:f64, y:f64) -> f64 {
-        let x = if x >= -1e-9 {x.round() as usize} else {return 0.0;};
-        let y = if y >= -1e-9 {y.round() as usize} else {return 0.0;};
-
-        self.get_default(x, y)
+        let height = |i:isize, j:isize| if i >= 0 && j >= 0 {Some(self.get_default(i as usize, j as usize))} else {None};
+        let heights = [height(x, y), height(x+1, y), height(x, y+1), height(x+1, y+1)];
+        let [p00, p10, p01, p11] = heights.map(|h| h.map(|h| h <= z).unwrap_or(false));
+        segments_from_cell(p00, p10, p01, p11, centre_below(heights, z), edge_middles(x, y))
     }
-}
-
-/// an iterator for iterate on the intersections between a half line and
-/// an height map
-pub struct IntersectionIterator {
-    line: HalfLine,
-    hmap: HeightMap
-}
 
-impl IntersectionIterator {
-    pub fn new(hmap: HeightMap, line:HalfLine) -> Self {
-        Self{line, hmap}
+    /// like `from_pixel_to_segments` with the boundary at the sub-pixel position where
+    /// the heights interpolated between the pixels are `z`, the pixels outside of the
+    /// map are above `z`
+    pub fn from_pixel_to_interpolated_segments(&self, x:isize, y:isize, z:f64) -> Case2<Segment> {
+        let height = |i:isize, j:isize| {
+            if i >= 0 && j >= 0 && (i as usize) < self.get_width() && (j as usize) < self.get_height() {
+                Some(self.get(i as usize, j as usize))
+            } else {None}
+        };
+        get_interpolated_segments_from_pixel(height, z, x, y)
     }
 
-    pub fn next(&mut self) -> Option<(Vec2, bool)> {
-        unimplemented!()
+    /// return the height at the continuous coordinates `(x, y)` with a bilinear
+    /// interpolation, the map is extended with the value of the nearest pixel
+    pub fn get_f64(&self, x:f64, y:f64) -> f64 {
+        self.sample(x, y, Interpolation::Bilinear, Edge::Clamp)
     }
-
-    pub fn get_hmap<'a>(&'a self) -> &'a HeightMap {&self.hmap}
-    pub fn get_half_line(&self) -> HalfLine {self.line}
 }
 
 #[cfg(test)]
@@ -299,12 +595,17 @@ mod tests {
         let line1 = HalfLine::new(Vec2::new(-1.0, -1.0), Vec2::new(1.0, 0.5));
         let line2 = HalfLine::new(Vec2::new(1.0, 0.0), Vec2::new(-1.0, 1.0));
 
-        let result = line1.plan_intersection(line2, 1e-6);
-
+        let result = line1.plan_intersection(line2);
 
         assert!(result.is_some());
         assert!(Vec2::new(1.0, 0.0) == line1.source() + line1.direction() * result.unwrap().get_x());
 
+        // nearly parallel lines still intersect, exactly parallel lines don't
+        let direction = Vec2::new(0.1, 0.3);
+        let nearly = Vec2::new(0.1, f64::from_bits(0.3f64.to_bits() + 1));
+        assert!(HalfLine::new(Vec2::new(0.0, 0.0), direction).plan_intersection(HalfLine::new(Vec2::new(1.0, 0.0), nearly)).is_some());
+        assert!(HalfLine::new(Vec2::new(0.0, 0.0), direction).plan_intersection(HalfLine::new(Vec2::new(1.0, 0.0), direction * 2.0)).is_none());
+
         let mut rng : rngs::ThreadRng = rand::thread_rng();
 
         for _ in 0..10000 {
@@ -318,7 +619,75 @@ mod tests {
                 Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0))
             );
 
-            let _ = line1.plan_intersection(line2, 1e-6);
+            // the solution is on both lines, up to the rounding
+            if let Some(solution) = line1.plan_intersection(line2) {
+                let p1 = line1.source() + line1.direction() * solution.get_x();
+                let p2 = line2.source() + line2.direction() * solution.get_y();
+                let scale = 1.0 + p1.norm() + line1.direction().cross(line2.direction()).abs().recip();
+                assert!(p1.distance(p2) <= 1e-12 * scale);
+            }
+        }
+    }
+
+    #[test]
+    fn test_segment_intersection() {
+        let s = Segment::new(Vec2::new(0.0, 0.0), Vec2::new(2.0, 2.0));
+        let p = |x, y| Vec2::new(x, y);
+
+        assert_eq!(s.intersection(&Segment::new(p(0.0, 2.0), p(2.0, 0.0))), Intersection::Point(p(1.0, 1.0)));
+        assert_eq!(s.intersection(&Segment::new(p(2.0, 2.0), p(3.0, 0.0))), Intersection::Point(p(2.0, 2.0)));
+        assert_eq!(s.intersection(&Segment::new(p(0.0, 1.0), p(1.0, 2.0))), Intersection::None);
+        assert_eq!(s.intersection(&Segment::new(p(3.0, 0.0), p(4.0, -1.0))), Intersection::None);
+
+        // collinear segments
+        assert_eq!(s.intersection(&Segment::new(p(3.0, 3.0), p(1.0, 1.0))), Intersection::Overlap(Segment::new(p(1.0, 1.0), p(2.0, 2.0))));
+        assert_eq!(s.intersection(&Segment::new(p(2.0, 2.0), p(3.0, 3.0))), Intersection::Point(p(2.0, 2.0)));
+        assert_eq!(s.intersection(&Segment::new(p(3.0, 3.0), p(4.0, 4.0))), Intersection::None);
+
+        assert_eq!(s.closest_point(p(2.0, 0.0)), p(1.0, 1.0));
+        assert_eq!(s.closest_point(p(-1.0, -3.0)), p(0.0, 0.0));
+        assert_eq!(s.distance_to(p(3.0, 2.0)), 1.0);
+
+        // on a grid of inexact coordinates, the intersections are consistent and the
+        // shared endpoints are exact
+        let mut rng : rngs::ThreadRng = rand::thread_rng();
+        for _ in 0..10000 {
+            let mut q = || p(rng.gen_range(0..4) as f64 * 0.1, rng.gen_range(0..4) as f64 * 0.1);
+            let (s1, s2) = (Segment::new(q(), q()), Segment::new(q(), q()));
+            if s1.source() == s1.target() || s2.source() == s2.target() {continue;}
+
+            let forward = s1.intersection(&s2);
+            assert_eq!(forward == Intersection::None, s2.intersection(&s1) == Intersection::None);
+            if s1.target() == s2.source() {
+                assert!(matches!(forward, Intersection::Point(x) if x == s1.target()) || matches!(forward, Intersection::Overlap(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_polygon() {
+        // an L-shaped polygon, clockwise with the y-axis pointing up
+        let l = Polygon::new(vec![
+            Vec2::new(0.0, 0.0), Vec2::new(0.0, 2.0), Vec2::new(1.0, 2.0),
+            Vec2::new(1.0, 1.0), Vec2::new(2.0, 1.0), Vec2::new(2.0, 0.0)
+        ]);
+        assert_eq!(l.signed_area(), -3.0);
+        assert!(!l.is_counter_clockwise() && l.reversed().is_counter_clockwise());
+        assert!(l.contains(Vec2::new(0.5, 1.5)) && !l.contains(Vec2::new(1.5, 1.5)));
+        assert_eq!(l.perimeter(), 8.0);
+        assert_eq!(l.bounding_box(), Some(BoundingBox{min:Vec2::new(0.0, 0.0), max:Vec2::new(2.0, 2.0)}));
+        assert_eq!(l.closest_point(Vec2::new(1.75, 1.5)), Some(Vec2::new(1.75, 1.0)));
+
+        // the boundary resampled every 0.3 at most
+        let polyline = l.to_polyline();
+        assert_eq!(polyline.length(), 8.0);
+        let resampled = polyline.resampled(0.3);
+        assert_eq!(resampled.points().len(), 28);
+        assert_eq!(resampled.points()[0], resampled.points()[27]);
+        for w in resampled.points().windows(2) {
+            assert!(w[0].distance(w[1]) <= 8.0 / 27.0 + 1e-12);
         }
+        assert!(resampled.points().iter().all(|p| l.closest_point(*p).unwrap().distance(*p) < 1e-12));
+        assert_eq!(polyline.point_at(2.5), Some(Vec2::new(0.5, 2.0)));
     }
 }
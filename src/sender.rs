@@ -0,0 +1,504 @@
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant};
+
+/// size in bytes of the serial receive buffer of GRBL
+pub const GRBL_BUFFER_SIZE : usize = 128;
+
+/// duration of silence of the controller before asking a status report
+const STATUS_INTERVAL : Duration = Duration::from_secs(1);
+
+/// a line sent by GRBL
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    /// the oldest line in the buffer is accepted
+    Ok,
+    /// the oldest line in the buffer is rejected with this error code
+    Error(u32),
+    /// the machine is in alarm, the program is aborted
+    Alarm(u32),
+    /// a status report `<...>`, without the angle brackets
+    Status(String),
+    /// any other line (welcome message, `[MSG:...]`, settings ...)
+    Message(String)
+}
+
+impl Response {
+    pub fn parse(line:&str) -> Self {
+        let line = line.trim();
+        if line == "ok" {
+            Response::Ok
+        } else if let Some(code) = line.strip_prefix("error:").and_then(|c| c.parse().ok()) {
+            Response::Error(code)
+        } else if let Some(code) = line.strip_prefix("ALARM:").and_then(|c| c.parse().ok()) {
+            Response::Alarm(code)
+        } else if line.starts_with('<') && line.ends_with('>') {
+            Response::Status(line[1..line.len()-1].to_string())
+        } else {
+            Response::Message(line.to_string())
+        }
+    }
+}
+
+/// remove the comments and the spaces of a line of G-code, GRBL ignores them
+/// and they would fill its buffer
+fn clean_line(line:&str) -> String {
+    let mut out = String::new();
+    let mut in_comment = false;
+
+    for c in line.chars() {
+        match c {
+            '(' => in_comment = true,
+            ')' => in_comment = false,
+            ';' if !in_comment => break,
+            c if !in_comment && !c.is_whitespace() => out.push(c),
+            _ => {}
+        }
+    }
+
+    out
+}
+
+/// send the real-time commands to GRBL, they are executed immediately
+/// even during the streaming of a program by another thread
+pub struct Realtime {
+    port : File
+}
+
+impl Realtime {
+    fn send(&mut self, byte:u8) -> Result<(), String> {
+        self.port.write_all(&[byte]).map_err(|e| format!("unable to write on the serial port: {}", e))
+    }
+
+    /// stop the motion with a controlled deceleration
+    pub fn feed_hold(&mut self) -> Result<(), String> {self.send(b'!')}
+
+    /// resume the motion after a feed hold
+    pub fn resume(&mut self) -> Result<(), String> {self.send(b'~')}
+
+    /// ask a status report, it is received as a `Response::Status`
+    pub fn request_status(&mut self) -> Result<(), String> {self.send(b'?')}
+}
+
+/// stream G-code programs to a GRBL controller with the character-counting protocol:
+/// the lines are sent as long as the lines not yet acknowledged fit in the buffer of GRBL
+pub struct GrblSender {
+    port : File,
+
+    /// size of the receive buffer of the controller
+    pub buffer_size : usize,
+
+    /// maximum duration to wait for a response, except while the machine is held
+    pub timeout : Duration,
+
+    /// the last status report received, without the angle brackets
+    state : Option<String>,
+
+    /// the bytes received after the last complete line
+    received : Vec<u8>,
+
+    /// the lines sent and not yet acknowledged, with their number in the program
+    pending : VecDeque<(usize, String)>
+}
+
+impl GrblSender {
+    /// open the serial device `path` (like `/dev/ttyUSB0`) in raw mode at 115200 bauds
+    pub fn open(path:&str) -> Result<Self, String> {
+        let port = OpenOptions::new().read(true).write(true).custom_flags(libc::O_NOCTTY).open(path)
+            .map_err(|e| format!("unable to open the serial port `{}`: {}", path, e))?;
+
+        let fd = port.as_raw_fd();
+        // SAFETY: `fd` is an open file descriptor, and `termios` is initialized by `tcgetattr`
+        let configured = unsafe {
+            let mut termios : libc::termios = std::mem::zeroed();
+            libc::tcgetattr(fd, &mut termios) == 0 && {
+                libc::cfmakeraw(&mut termios);
+                termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+                libc::cfsetspeed(&mut termios, libc::B115200) == 0 &&
+                libc::tcsetattr(fd, libc::TCSANOW, &termios) == 0
+            }
+        };
+        if !configured {
+            return Err(format!("unable to configure the serial port `{}`", path));
+        }
+
+        Ok(Self::new(port))
+    }
+
+    /// use an already configured port
+    pub fn new(port:File) -> Self {
+        GrblSender{port, buffer_size:GRBL_BUFFER_SIZE, timeout:Duration::from_secs(60), state:None, received:vec![], pending:VecDeque::new()}
+    }
+
+    /// return a handle to send the real-time commands from another thread
+    pub fn realtime(&self) -> Result<Realtime, String> {
+        let port = self.port.try_clone().map_err(|e| format!("unable to share the serial port: {}", e))?;
+        Ok(Realtime{port})
+    }
+
+    /// return the number of bytes in the buffer of the controller
+    fn in_flight(&self) -> usize {
+        self.pending.iter().map(|(_, line)| line.len() + 1).sum()
+    }
+
+    /// return the next line sent by the controller, or `None` after `timeout`
+    fn read_line(&mut self, timeout:Duration) -> Result<Option<String>, String> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(end) = self.received.iter().position(|b| *b == b'\n') {
+                let line : Vec<u8> = self.received.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line).trim().to_string();
+                if line.is_empty() {continue;}
+                return Ok(Some(line));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut poll = libc::pollfd{fd:self.port.as_raw_fd(), events:libc::POLLIN, revents:0};
+            // SAFETY: `poll` is a valid `pollfd` for the duration of the call
+            let ready = unsafe {libc::poll(&mut poll, 1, remaining.as_millis() as libc::c_int)};
+            if ready < 0 {return Err("unable to wait on the serial port".to_string());}
+            if ready == 0 {return Ok(None);}
+
+            let mut buffer = [0; 256];
+            match self.port.read(&mut buffer) {
+                Ok(0) => return Err("the serial port is closed".to_string()),
+                Ok(n) => self.received.extend_from_slice(&buffer[..n]),
+                Err(e) => return Err(format!("unable to read on the serial port: {}", e))
+            }
+        }
+    }
+
+    /// read and handle one response, waiting at most `timeout`,
+    /// return `None` if no response arrived
+    fn handle_response<F>(&mut self, timeout:Duration, observer:&mut F) -> Result<Option<Response>, String>
+        where
+            F: FnMut(&Response)
+    {
+        let line = match self.read_line(timeout)? {
+            Some(line) => line,
+            None => return Ok(None)
+        };
+
+        let response = Response::parse(&line);
+        observer(&response);
+
+        match &response {
+            Response::Status(state) => self.state = Some(state.clone()),
+            Response::Ok => {
+                self.pending.pop_front();
+            },
+            Response::Error(code) => {
+                let (n, line) = self.pending.pop_front().unwrap_or((0, String::new()));
+                let error = format!("the line {} `{}` is rejected with the error {}", n, line, code);
+                self.drain(observer);
+                return Err(error);
+            },
+            Response::Alarm(code) => {
+                // the alarm resets the controller, which flushes its buffer
+                self.pending.clear();
+                return Err(format!("the machine is in alarm {}", code));
+            },
+            _ => {}
+        }
+
+        Ok(Some(response))
+    }
+
+    /// return `true` if the last status report is a feed hold or an open safety door,
+    /// the controller doesn't answer until the motion is resumed
+    fn is_held(&self) -> bool {
+        self.state.as_deref().map(|s| s.starts_with("Hold") || s.starts_with("Door")).unwrap_or(false)
+    }
+
+    /// wait for a response other than a status report, asking a status report after each
+    /// `STATUS_INTERVAL` of silence, return `false` if the controller is silent for `timeout`
+    /// and answers the last request while not held, or doesn't answer it
+    fn wait_response<F>(&mut self, observer:&mut F) -> Result<bool, String>
+        where
+            F: FnMut(&Response)
+    {
+        let mut since = Instant::now();
+        let mut asked = false;
+        loop {
+            match self.handle_response(Duration::min(self.timeout, STATUS_INTERVAL), observer)? {
+                Some(Response::Status(_)) => if self.is_held() {
+                    since = Instant::now();
+                    asked = false;
+                },
+                Some(_) => return Ok(true),
+                None => {
+                    if asked && since.elapsed() >= self.timeout {return Ok(false);}
+                    self.port.write_all(b"?").map_err(|e| format!("unable to write on the serial port: {}", e))?;
+                    asked = true;
+                }
+            }
+        }
+    }
+
+    /// wait for the responses to the lines still in the buffer of the controller,
+    /// such that they are not taken for the responses to the next lines, the lines
+    /// not answered stay pending and are counted in the buffer of the next program
+    fn drain<F>(&mut self, observer:&mut F)
+        where
+            F: FnMut(&Response)
+    {
+        while !self.pending.is_empty() {
+            let line = match self.read_line(Duration::min(self.timeout, Duration::from_secs(1))) {
+                Ok(Some(line)) => line,
+                _ => break
+            };
+            let response = Response::parse(&line);
+            observer(&response);
+            if let Response::Ok | Response::Error(_) = response {self.pending.pop_front();}
+        }
+    }
+
+    /// stream a program, `observer` receives every response of the controller (including
+    /// the status reports requested with `Realtime` or while waiting), return the number
+    /// of lines sent, the streaming stops at the first error or alarm
+    pub fn stream<F>(&mut self, program:&str, mut observer:F) -> Result<usize, String>
+        where
+            F: FnMut(&Response)
+    {
+        let result = self.stream_lines(program, &mut observer);
+        // the controller still answers the lines in its buffer after an error
+        if result.is_err() {self.drain(&mut observer);}
+        result
+    }
+
+    fn stream_lines<F>(&mut self, program:&str, observer:&mut F) -> Result<usize, String>
+        where
+            F: FnMut(&Response)
+    {
+        let lines : Vec<(usize, String)> = program.lines().map(clean_line).enumerate()
+            .filter(|(_, line)| !line.is_empty()).collect();
+        if let Some((n, _)) = lines.iter().find(|(_, line)| line.len() + 1 > self.buffer_size) {
+            return Err(format!("the line {} is longer than the buffer of the controller", n + 1));
+        }
+
+        let mut sent = 0;
+        for (n, line) in lines {
+            while self.in_flight() + line.len() + 1 > self.buffer_size {
+                if !self.wait_response(observer)? {
+                    return Err(format!("no response of the controller after the line {}", n + 1));
+                }
+            }
+
+            self.port.write_all(format!("{}\n", line).as_bytes())
+                .map_err(|e| format!("unable to write on the serial port: {}", e))?;
+            self.pending.push_back((n + 1, line));
+            sent += 1;
+
+            // handle the responses already received
+            while self.handle_response(Duration::ZERO, observer)?.is_some() {}
+        }
+
+        while !self.pending.is_empty() {
+            if !self.wait_response(observer)? {
+                return Err("no response of the controller at the end of the program".to_string());
+            }
+        }
+
+        Ok(sent)
+    }
+
+    /// ask a status report and wait for it, return its content without the angle brackets
+    pub fn status(&mut self) -> Result<String, String> {
+        self.realtime()?.request_status()?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut status = None;
+        while status.is_none() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.read_line(remaining)? {
+                Some(line) => if let Response::Status(s) = Response::parse(&line) {
+                    self.state = Some(s.clone());
+                    status = Some(s);
+                },
+                None => return Err("no status report from the controller".to_string())
+            }
+        }
+
+        Ok(status.unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sender::*;
+    use std::os::unix::io::FromRawFd;
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    /// a simulated GRBL on the master side of a pseudo-terminal, it processes a line
+    /// every millisecond (a dwell `G4` takes 300 milliseconds) and records the maximum number of bytes waiting in its buffer
+    fn simulate(mut master:File, max_fill:Arc<AtomicUsize>) {
+        let mut buffer : Vec<u8> = vec![];
+        let mut hold = false;
+
+        loop {
+            let mut poll = libc::pollfd{fd:master.as_raw_fd(), events:libc::POLLIN, revents:0};
+            // SAFETY: `poll` is a valid `pollfd` for the duration of the call
+            if unsafe {libc::poll(&mut poll, 1, 1)} > 0 {
+                let mut bytes = [0; 256];
+                match master.read(&mut bytes) {
+                    Ok(n) if n > 0 => {
+                        for b in &bytes[..n] {
+                            match b {
+                                b'?' => {
+                                    let state = if hold {"Hold:0"} else {"Idle"};
+                                    write!(master, "<{}|MPos:0.000,0.000,0.000|FS:0,0>\r\n", state).unwrap();
+                                },
+                                b'!' => hold = true,
+                                b'~' => hold = false,
+                                b => buffer.push(*b)
+                            }
+                        }
+                        max_fill.fetch_max(buffer.len(), Ordering::SeqCst);
+                    },
+                    _ => return
+                }
+            }
+
+            if hold {continue;}
+            if let Some(end) = buffer.iter().position(|b| *b == b'\n') {
+                let line : Vec<u8> = buffer.drain(..=end).collect();
+                let line = String::from_utf8(line).unwrap();
+                std::thread::sleep(Duration::from_millis(1));
+
+                if line.starts_with("G4") {std::thread::sleep(Duration::from_millis(300));}
+
+                let response = if line.starts_with("G99") {"error:20"}
+                    else if line.starts_with("M999") {"ALARM:1"}
+                    else {"ok"};
+                write!(master, "{}\r\n", response).unwrap();
+            }
+        }
+    }
+
+    /// open a pseudo-terminal with a simulated GRBL, return the path of the device
+    /// and the maximum fill of the buffer of the simulation
+    fn open_simulation() -> (String, File, Arc<AtomicUsize>) {
+        let (mut master, mut slave) = (0, 0);
+        // SAFETY: the pointers are valid, the names and the settings are not used
+        let result = unsafe {
+            libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null(), std::ptr::null())
+        };
+        assert_eq!(result, 0);
+
+        // SAFETY: `slave` is an open terminal, `ttyname` returns a null terminated string
+        let path = unsafe {std::ffi::CStr::from_ptr(libc::ttyname(slave))}.to_str().unwrap().to_string();
+
+        // SAFETY: the descriptors are open and owned by the returned files
+        let (master, slave) = unsafe {(File::from_raw_fd(master), File::from_raw_fd(slave))};
+
+        let max_fill = Arc::new(AtomicUsize::new(0));
+        let fill = max_fill.clone();
+        std::thread::spawn(move || simulate(master, fill));
+
+        (path, slave, max_fill)
+    }
+
+    #[test]
+    fn test_stream() {
+        let (path, _slave, max_fill) = open_simulation();
+        let mut sender = GrblSender::open(&path).unwrap();
+
+        let mut program = String::from("(a comment)\nG21 G90 ; mm\n\n");
+        for i in 0..200 {
+            program.push_str(&format!("G1 X{}.000 Y{}.000 F600.0\n", i, 2 * i));
+        }
+
+        let mut oks = 0;
+        let sent = sender.stream(&program, |r| if *r == Response::Ok {oks += 1;}).unwrap();
+        assert_eq!(sent, 201);
+        assert_eq!(oks, 201);
+
+        // the buffer is filled ahead of the execution, without overflowing
+        let max_fill = max_fill.load(Ordering::SeqCst);
+        assert!(max_fill <= GRBL_BUFFER_SIZE && max_fill > GRBL_BUFFER_SIZE / 2);
+
+        // feed hold and resume
+        assert!(sender.status().unwrap().starts_with("Idle|"));
+        sender.realtime().unwrap().feed_hold().unwrap();
+        assert!(sender.status().unwrap().starts_with("Hold:0|"));
+        sender.realtime().unwrap().resume().unwrap();
+        assert!(sender.status().unwrap().starts_with("Idle|"));
+    }
+
+    #[test]
+    fn test_hold() {
+        let (path, _slave, _) = open_simulation();
+        let mut sender = GrblSender::open(&path).unwrap();
+        sender.timeout = Duration::from_millis(200);
+
+        // a feed hold longer than the timeout doesn't abort the program
+        let mut realtime = sender.realtime().unwrap();
+        realtime.feed_hold().unwrap();
+        let resume = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(1500));
+            realtime.resume().unwrap();
+        });
+
+        let mut program = String::new();
+        for i in 0..50 {
+            program.push_str(&format!("G1 X{}.000 F600.0\n", i));
+        }
+        let mut held = 0;
+        let sent = sender.stream(&program, |r| if let Response::Status(s) = r {
+            if s.starts_with("Hold") {held += 1;}
+        }).unwrap();
+        assert_eq!(sent, 50);
+        assert!(held > 0);
+        resume.join().unwrap();
+    }
+
+    #[test]
+    fn test_timeout() {
+        let (path, _slave, max_fill) = open_simulation();
+        let mut sender = GrblSender::open(&path).unwrap();
+        sender.timeout = Duration::from_millis(100);
+
+        // the controller is silent during the dwell, the lines in its buffer
+        // are still counted in the next program
+        let mut program = String::from("G4 P1\n");
+        for i in 0..50 {
+            program.push_str(&format!("G1 X{}.000 Y{}.000 F600.0\n", i, 2 * i));
+        }
+        assert!(sender.stream(&program, |_| {}).unwrap_err().contains("no response"));
+
+        assert_eq!(sender.stream(&program[6..], |_| {}).unwrap(), 50);
+        assert!(max_fill.load(Ordering::SeqCst) <= GRBL_BUFFER_SIZE);
+        assert_eq!(sender.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let (path, _slave, max_fill) = open_simulation();
+        let mut sender = GrblSender::open(&path).unwrap();
+
+        let error = sender.stream("G0 X1\nG99\nG0 X2\n", |_| {}).unwrap_err();
+        assert!(error.contains("line 2 `G99`") && error.contains("error 20"));
+
+        let alarm = sender.stream("G0 X1\nM999\n", |_| {}).unwrap_err();
+        assert!(alarm.contains("alarm 1"));
+
+        // a line too long stops the program before any line is sent
+        let long = format!("G0 X1\nG1 X{}\n", "1".repeat(GRBL_BUFFER_SIZE));
+        assert!(sender.stream(&long, |_| {}).unwrap_err().contains("line 2"));
+        assert_eq!(sender.in_flight(), 0);
+
+        let mut program = String::new();
+        for i in 0..100 {
+            program.push_str(&format!("G1 X{}.000 Y{}.000 F600.0\n", i, 2 * i));
+        }
+        assert_eq!(sender.stream(&program, |_| {}).unwrap(), 100);
+        assert!(max_fill.load(Ordering::SeqCst) <= GRBL_BUFFER_SIZE);
+
+        assert_eq!(clean_line("G1 X1 (move) Y2 ; end"), "G1X1Y2");
+        assert_eq!(Response::parse("<Run|MPos:1,2,3>"), Response::Status("Run|MPos:1,2,3".to_string()));
+    }
+}
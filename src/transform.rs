@@ -0,0 +1,262 @@
+use crate::height_map::*;
+use crate::parse_config::Transform;
+
+/// the physical size in `m` of a map along the x-axis and the y-axis,
+/// it must be transformed along with the map to keep the size of a pixel consistent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Extent {
+    pub width : f64,
+    pub height : f64
+}
+
+impl Extent {
+    pub fn new(width:f64, height:f64) -> Self {
+        Extent{width, height}
+    }
+
+    /// return the size in `m` of a pixel of `hmap` along the x-axis and the y-axis
+    pub fn pixel_size(&self, hmap:&HeightMap) -> (f64, f64) {
+        (self.width / hmap.get_width() as f64, self.height / hmap.get_height() as f64)
+    }
+}
+
+impl HeightMap {
+    /// return the region of size `width * height` whose top-left pixel is `(x, y)`
+    pub fn crop(&self, x:usize, y:usize, width:usize, height:usize) -> Self {
+        assert!(x + width <= self.get_width() && y + height <= self.get_height());
+
+        let mut out = Self::new(width, height);
+        for i in 0..width {
+            for j in 0..height {
+                out.unsafe_set(i, j, self.unsafe_get(x + i, y + j));
+            }
+        }
+        out
+    }
+
+    /// add `left`, `top`, `right` and `bottom` pixels filled with `fill` around the map
+    pub fn pad(&self, left:usize, top:usize, right:usize, bottom:usize, fill:f64) -> Self {
+        let mut out = Self::new_with_buffer(
+            self.get_width() + left + right,
+            self.get_height() + top + bottom,
+            vec![fill; (self.get_width() + left + right) * (self.get_height() + top + bottom)]
+        );
+        for i in 0..self.get_width() {
+            for j in 0..self.get_height() {
+                out.unsafe_set(i + left, j + top, self.unsafe_get(i, j));
+            }
+        }
+        out
+    }
+
+    /// return the map resampled to `width * height` pixels, the borders
+    /// of the new map are aligned with the borders of `self`
+    pub fn resample(&self, width:usize, height:usize, interpolation:Interpolation) -> Self {
+        assert!(width > 0 && height > 0);
+        assert!(self.get_width() > 0 && self.get_height() > 0);
+
+        let sx = self.get_width() as f64 / width as f64;
+        let sy = self.get_height() as f64 / height as f64;
+
+        Self::new(width, height).par_map_pixels(|_, x, y| {
+            self.sample((x as f64 + 0.5) * sx - 0.5, (y as f64 + 0.5) * sy - 0.5, interpolation, Edge::Clamp)
+        })
+    }
+
+    /// rotate the map by `quarter_turns` quarters of turn counter-clockwise
+    /// (as seen on the image, with the y-axis pointing down)
+    pub fn rotate90(&self, quarter_turns:isize) -> Self {
+        let (w, h) = (self.get_width(), self.get_height());
+
+        match quarter_turns.rem_euclid(4) {
+            0 => self.crop(0, 0, w, h),
+            1 => Self::new(h, w).par_map_pixels(|_, x, y| self.unsafe_get(w - 1 - y as usize, x as usize)),
+            2 => Self::new(w, h).par_map_pixels(|_, x, y| self.unsafe_get(w - 1 - x as usize, h - 1 - y as usize)),
+            _ => Self::new(h, w).par_map_pixels(|_, x, y| self.unsafe_get(y as usize, h - 1 - x as usize))
+        }
+    }
+
+    /// rotate the map by `angle` radians counter-clockwise (as seen on the image,
+    /// with the y-axis pointing down) around its center, the new map contains all
+    /// the rotated map and its corners are filled with `fill`,
+    /// the pixels must be square
+    pub fn rotate(&self, angle:f64, fill:f64, interpolation:Interpolation) -> Self {
+        let (w, h) = (self.get_width() as f64, self.get_height() as f64);
+        let (sin, cos) = angle.sin_cos();
+
+        let new_w = (w * cos.abs() + h * sin.abs() - 1e-9).ceil().max(1.0) as usize;
+        let new_h = (w * sin.abs() + h * cos.abs() - 1e-9).ceil().max(1.0) as usize;
+
+        let (cx, cy) = ((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+        let (ncx, ncy) = ((new_w as f64 - 1.0) / 2.0, (new_h as f64 - 1.0) / 2.0);
+
+        Self::new(new_w, new_h).par_map_pixels(|_, x, y| {
+            let (u, v) = (x as f64 - ncx, y as f64 - ncy);
+            self.sample(cx + u * cos - v * sin, cy + u * sin + v * cos, interpolation, Edge::Fill(fill))
+        })
+    }
+
+    /// reverse the map along the x-axis (left becomes right)
+    pub fn mirror_x(&self) -> Self {
+        let w = self.get_width();
+        self.par_map_pixels(|hmap, x, y| hmap.unsafe_get(w - 1 - x as usize, y as usize))
+    }
+
+    /// reverse the map along the y-axis (top becomes bottom)
+    pub fn mirror_y(&self) -> Self {
+        let h = self.get_height();
+        self.par_map_pixels(|hmap, x, y| hmap.unsafe_get(x as usize, h - 1 - y as usize))
+    }
+
+    /// apply a transformation to the map of physical size `extent`,
+    /// return the new map and its physical size
+    pub fn transform(&self, transform:&Transform, extent:Extent) -> (Self, Extent) {
+        let (px, py) = extent.pixel_size(self);
+
+        // size in `m` of a map of `w * h` pixels with the current pixel size
+        let extent_of = |w:usize, h:usize| Extent::new(w as f64 * px, h as f64 * py);
+
+        match *transform {
+            Transform::Crop(x, y, w, h) => (self.crop(x, y, w, h), extent_of(w, h)),
+            Transform::Pad(left, top, right, bottom, fill) => {
+                let out = self.pad(left, top, right, bottom, fill);
+                let out_extent = extent_of(out.get_width(), out.get_height());
+                (out, out_extent)
+            },
+            Transform::Resample(w, h, interpolation) => (self.resample(w, h, interpolation), extent),
+            Transform::Resolution(size, interpolation) => {
+                let w = usize::max(1, (extent.width / size).round() as usize);
+                let h = usize::max(1, (extent.height / size).round() as usize);
+                (self.resample(w, h, interpolation), extent)
+            },
+            Transform::Rotate(angle, fill, interpolation) => {
+                // the rotation is done with square pixels
+                let size = f64::min(px, py);
+                let square = if (px - py).abs() > 1e-9 * size {
+                    self.transform(&Transform::Resolution(size, interpolation), extent).0
+                } else {self.crop(0, 0, self.get_width(), self.get_height())};
+
+                let quarter = angle / std::f64::consts::FRAC_PI_2;
+                let out = if (quarter - quarter.round()).abs() < 1e-9 {
+                    square.rotate90(quarter.round() as isize)
+                } else {
+                    square.rotate(angle, fill, interpolation)
+                };
+
+                let (sx, sy) = Extent::new(extent.width, extent.height).pixel_size(&square);
+                let out_extent = Extent::new(out.get_width() as f64 * sx, out.get_height() as f64 * sy);
+                (out, out_extent)
+            },
+            Transform::MirrorX => (self.mirror_x(), extent),
+            Transform::MirrorY => (self.mirror_y(), extent),
+            Transform::Fit(width, height, fill) => {
+                // scale the design to fit in the stock, keep its resolution
+                // in pixels and center it
+                let scale = f64::min(width / extent.width, height / extent.height);
+                let (px, py) = (px * scale, py * scale);
+
+                let pad_x = usize::max(self.get_width(), (width / px).round() as usize) - self.get_width();
+                let pad_y = usize::max(self.get_height(), (height / py).round() as usize) - self.get_height();
+
+                let out = self.pad(pad_x / 2, pad_y / 2, pad_x - pad_x / 2, pad_y - pad_y / 2, fill);
+                let out_extent = Extent::new(out.get_width() as f64 * px, out.get_height() as f64 * py);
+                (out, out_extent)
+            }
+        }
+    }
+
+    /// apply a list of transformations in order to the map of physical size `extent`,
+    /// return the new map and its physical size, or an error if a crop is outside of the map
+    pub fn transforms(&self, transforms:&[Transform], extent:Extent) -> Result<(Self, Extent), String> {
+        let mut out = (self.crop(0, 0, self.get_width(), self.get_height()), extent);
+        for (i, t) in transforms.iter().enumerate() {
+            if let Transform::Crop(x, y, width, height) = *t {
+                if x + width > out.0.get_width() || y + height > out.0.get_height() {
+                    return Err(format!("the crop of the transform {} is outside of the {} x {} map", i, out.0.get_width(), out.0.get_height()));
+                }
+            }
+            out = out.0.transform(t, out.1);
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transform::*;
+
+    fn ramp(width:usize, height:usize) -> HeightMap {
+        let mut hmap = HeightMap::new(width, height);
+        for i in 0..width {
+            for j in 0..height {
+                hmap.set(i, j, i as f64 + 10.0 * j as f64);
+            }
+        }
+        hmap
+    }
+
+    fn same(h1:&HeightMap, h2:&HeightMap) -> bool {
+        h1.get_width() == h2.get_width() && h1.get_height() == h2.get_height() &&
+        (0..h1.get_width()).all(|i| (0..h1.get_height()).all(|j| f64::abs(h1.get(i, j) - h2.get(i, j)) < 1e-9))
+    }
+
+    #[test]
+    fn test_rotate_and_mirror() {
+        let hmap = ramp(4, 3);
+
+        let r1 = hmap.rotate90(1);
+        assert_eq!((r1.get_width(), r1.get_height()), (3, 4));
+        // the top-right pixel becomes the top-left one
+        assert_eq!(r1.get(0, 0), hmap.get(3, 0));
+        assert!(same(&r1.rotate90(3), &hmap));
+        assert!(same(&hmap.rotate90(2), &hmap.mirror_x().mirror_y()));
+
+        // an arbitrary rotation of a quarter of turn is the same as `rotate90`
+        let r2 = hmap.rotate(std::f64::consts::FRAC_PI_2, 0.0, Interpolation::Bilinear);
+        assert!(same(&r1, &r2));
+    }
+
+    #[test]
+    fn test_resample() {
+        let hmap = ramp(8, 8);
+
+        // a linear ramp is kept by the bilinear and bicubic interpolations
+        // (far enough from the borders)
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            let out = hmap.resample(14, 14, interpolation);
+            let coord = |i:usize| (i as f64 + 0.5) * 8.0 / 14.0 - 0.5;
+            for i in 4..10 {
+                assert!(f64::abs(out.get(i, 7) - coord(i) - 10.0 * coord(7)) < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn test_extent() {
+        let hmap = ramp(10, 20);
+        let extent = Extent::new(1.0, 2.0);
+
+        let (out, out_extent) = hmap.transforms(&[
+            Transform::Crop(2, 4, 5, 10),
+            Transform::Pad(1, 1, 0, 0, 0.0),
+            Transform::Rotate(std::f64::consts::FRAC_PI_2, 0.0, Interpolation::Nearest),
+            Transform::Resolution(0.05, Interpolation::Bilinear)
+        ], extent).unwrap();
+
+        assert_eq!((out.get_width(), out.get_height()), (22, 12));
+        assert!(f64::abs(out_extent.width - 1.1) < 1e-9);
+        assert!(f64::abs(out_extent.height - 0.6) < 1e-9);
+
+        // the crop is outside of the padded map
+        assert!(hmap.transforms(&[
+            Transform::Pad(1, 0, 0, 0, 0.0),
+            Transform::Crop(2, 0, 10, 20)
+        ], extent).is_err());
+
+        // a 1 x 2 design fitted in a 4 x 4 stock
+        let (out, out_extent) = hmap.transform(&Transform::Fit(4.0, 4.0, 0.0), extent);
+        assert_eq!((out.get_width(), out.get_height()), (20, 20));
+        assert!(f64::abs(out_extent.width - 4.0) < 1e-9);
+        assert_eq!(out.get(5, 0), hmap.get(0, 0));
+    }
+}
@@ -0,0 +1,422 @@
+use crate::bit_map::{Move, Path};
+use crate::segment::Vec2;
+
+/// the changes allowed on the independent sub-paths of a path to reduce its rapid moves
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TravelOptions {
+    /// allow to run an open sub-path backward, this changes its cutting direction
+    pub reverse : bool,
+
+    /// allow to start a closed loop at any of its vertices
+    pub rotate_loops : bool
+}
+
+/// total length of the rapid moves of a path before and after the optimization
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TravelReport {
+    pub before : f64,
+    pub after : f64
+}
+
+/// a sub-path starting with a rapid move, the rapid move is not in `moves`
+struct Block {
+    start : Vec2,
+    end : Vec2,
+    moves : Vec<Move>,
+    /// the points of the sub-path if it is cut at a single depth:
+    /// the moves are some `Zmove`, then `XYmove` to each of these points, then some `Zmove`
+    single_level : Option<(usize, Vec<Vec2>, usize)>
+}
+
+/// the way a block is run
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Variant {
+    Forward,
+    Backward,
+    /// start a closed loop at its vertex of this index
+    Rotated(usize)
+}
+
+impl Block {
+    fn new(start:Vec2, moves:Vec<Move>) -> Self {
+        let mut end = start;
+        for m in moves.iter() {
+            if let Move::XYmove(x, y) | Move::FXYmove(x, y) | Move::XYZmove(x, y, _) = m {end = Vec2::new(*x, *y);}
+        }
+
+        let down = moves.iter().take_while(|m| matches!(m, Move::Zmove(_))).count();
+        let up = moves[down..].iter().rev().take_while(|m| matches!(m, Move::Zmove(_))).count();
+        let middle = &moves[down..moves.len() - up];
+
+        let single_level = if !middle.is_empty() && middle.iter().all(|m| matches!(m, Move::XYmove(_, _))) {
+            let mut points = vec![start];
+            points.extend(middle.iter().map(|m| match m {
+                Move::XYmove(x, y) => Vec2::new(*x, *y),
+                _ => unreachable!()
+            }));
+            Some((down, points, up))
+        } else {None};
+
+        Block{start, end, moves, single_level}
+    }
+
+    fn is_loop(&self) -> bool {
+        matches!(&self.single_level, Some((_, points, _)) if points.len() > 2 && points[0] == points[points.len()-1])
+    }
+
+    fn can_reverse(&self) -> bool {
+        self.single_level.is_some()
+    }
+
+    fn entry(&self, variant:Variant) -> Vec2 {
+        match variant {
+            Variant::Forward => self.start,
+            Variant::Backward => self.end,
+            Variant::Rotated(i) => self.single_level.as_ref().unwrap().1[i]
+        }
+    }
+
+    fn exit(&self, variant:Variant) -> Vec2 {
+        match variant {
+            Variant::Forward => self.end,
+            Variant::Backward => self.start,
+            Variant::Rotated(i) => self.single_level.as_ref().unwrap().1[i]
+        }
+    }
+
+    /// return the variants allowed by the options
+    fn variants(&self, options:TravelOptions) -> Vec<Variant> {
+        let mut variants = vec![Variant::Forward];
+        if self.is_loop() && options.rotate_loops {
+            let n = self.single_level.as_ref().unwrap().1.len() - 1;
+            variants.extend((1..n).map(Variant::Rotated));
+        } else if self.can_reverse() && options.reverse && self.start != self.end {
+            variants.push(Variant::Backward);
+        }
+        variants
+    }
+
+    /// push the moves of the block run with `variant`, starting by a rapid move
+    fn push_moves(&self, variant:Variant, out:&mut Vec<Move>) {
+        let entry = self.entry(variant);
+        out.push(Move::FXYmove(entry.get_x(), entry.get_y()));
+
+        let (down, points, up) = match (variant, &self.single_level) {
+            (Variant::Forward, _) | (_, None) => {
+                out.extend(self.moves.iter().copied());
+                return;
+            },
+            (_, Some(level)) => level
+        };
+
+        let n = self.moves.len();
+        out.extend(self.moves[..*down].iter().copied());
+
+        let order : Vec<Vec2> = match variant {
+            Variant::Backward => points.iter().rev().skip(1).copied().collect(),
+            Variant::Rotated(i) => {
+                // the loop is `points[0], ..., points[m] = points[0]`
+                let m = points.len() - 1;
+                (1..=m).map(|k| points[(i + k) % m]).collect()
+            },
+            Variant::Forward => unreachable!()
+        };
+        out.extend(order.into_iter().map(|p| Move::XYmove(p.get_x(), p.get_y())));
+
+        out.extend(self.moves[n - up..].iter().copied());
+    }
+}
+
+impl Path {
+    /// return the total length of the rapid moves (`FXYmove`) of the path
+    pub fn rapid_length(&self) -> f64 {
+        let mut position = Vec2::new(self.x_init, self.y_init);
+        let mut length = 0.0;
+
+        for m in self.path.iter() {
+            match m {
+                Move::FXYmove(x, y) => {
+                    let target = Vec2::new(*x, *y);
+                    length += position.distance(target);
+                    position = target;
+                },
+                Move::XYmove(x, y) | Move::XYZmove(x, y, _) => position = Vec2::new(*x, *y),
+                Move::Zmove(_) | Move::Feed(_) => {}
+            }
+        }
+
+        length
+    }
+
+    /// reorder the independent sub-paths of the path (the moves between two rapid moves)
+    /// to reduce the total length of the rapid moves, with a nearest neighbour heuristic
+    /// improved by 2-opt, and return the new path with the rapid length before and after,
+    ///
+    /// the sub-paths are independent if all the rapid moves are done at the same height
+    /// and all the sub-paths end at this height, otherwise the path is returned unchanged;
+    /// the moves before the first rapid move and the rapid moves that end the path
+    /// without cutting after them are kept in place
+    pub fn optimize_travel(&self, options:TravelOptions) -> (Path, TravelReport) {
+        let before = self.rapid_length();
+        let unchanged = (self.clone(), TravelReport{before, after:before});
+
+        // split the path in a prefix, blocks and a suffix
+        let first = match self.path.iter().position(|m| matches!(m, Move::FXYmove(_, _))) {
+            Some(first) => first,
+            None => return unchanged
+        };
+
+        let mut z = self.z_init;
+        let mut rapid_z = None;
+        let mut position = Vec2::new(self.x_init, self.y_init);
+        let mut prefix_end = position;
+        let mut blocks : Vec<Block> = vec![];
+        let mut current : Option<(Vec2, Vec<Move>)> = None;
+
+        for (i, m) in self.path.iter().enumerate() {
+            if i == first {prefix_end = position;}
+            match m {
+                Move::FXYmove(x, y) => {
+                    if *rapid_z.get_or_insert(z) != z {return unchanged;}
+                    if let Some((start, moves)) = current.take() {
+                        blocks.push(Block::new(start, moves));
+                    }
+                    position = Vec2::new(*x, *y);
+                    current = Some((position, vec![]));
+                },
+                _ => {
+                    if let Move::XYmove(x, y) | Move::XYZmove(x, y, _) = m {position = Vec2::new(*x, *y);}
+                    if let Move::Zmove(new_z) | Move::XYZmove(_, _, new_z) = m {z = *new_z;}
+                    if let Some((_, moves)) = current.as_mut() {moves.push(*m);}
+                }
+            }
+        }
+        if let Some((start, moves)) = current.take() {
+            blocks.push(Block::new(start, moves));
+        }
+
+        // every sub-path must end at the height of the rapid moves
+        let mut z = rapid_z.unwrap();
+        for block in blocks.iter() {
+            for m in block.moves.iter() {
+                if let Move::Zmove(new_z) | Move::XYZmove(_, _, new_z) = m {z = *new_z;}
+            }
+            if z != rapid_z.unwrap() {return unchanged;}
+        }
+
+        let cutting = |b:&Block| b.moves.iter().any(|m| matches!(m, Move::XYmove(_, _) | Move::XYZmove(_, _, _)));
+        let suffix_len = blocks.iter().rev().take_while(|b| !cutting(b)).count();
+        let suffix = blocks.split_off(blocks.len() - suffix_len);
+
+        let order = Self::nearest_neighbour(&blocks, prefix_end, options);
+        let order = Self::two_opt(&blocks, order, prefix_end, options);
+        let order = Self::refine_loops(&blocks, order, prefix_end, options);
+
+        let mut path = self.path[..first].to_vec();
+        for (i, variant) in order.iter() {
+            blocks[*i].push_moves(*variant, &mut path);
+        }
+        for block in suffix.iter() {
+            block.push_moves(Variant::Forward, &mut path);
+        }
+
+        let out = Path{x_init:self.x_init, y_init:self.y_init, z_init:self.z_init, path};
+        let after = out.rapid_length();
+
+        // the heuristics can't be worse than the initial order, but keep it in case of rounding
+        if after > before {return unchanged;}
+        (out, TravelReport{before, after})
+    }
+
+    /// build an order of the blocks by going each time to the nearest entry
+    fn nearest_neighbour(blocks:&[Block], start:Vec2, options:TravelOptions) -> Vec<(usize, Variant)> {
+        let mut visited = vec![false; blocks.len()];
+        let mut order = vec![];
+        let mut position = start;
+
+        for _ in 0..blocks.len() {
+            let mut best : Option<(f64, usize, Variant)> = None;
+            for (i, block) in blocks.iter().enumerate() {
+                if visited[i] {continue;}
+                for variant in block.variants(options) {
+                    let d = position.distance(block.entry(variant));
+                    if best.map(|(bd, _, _)| d < bd).unwrap_or(true) {best = Some((d, i, variant));}
+                }
+            }
+
+            let (_, i, variant) = best.unwrap();
+            visited[i] = true;
+            position = blocks[i].exit(variant);
+            order.push((i, variant));
+        }
+
+        order
+    }
+
+    /// improve an order by reversing sub-sequences of blocks while it reduces
+    /// the rapid length, the blocks of a reversed sub-sequence are run backward
+    fn two_opt(blocks:&[Block], mut order:Vec<(usize, Variant)>, start:Vec2, options:TravelOptions) -> Vec<(usize, Variant)> {
+        let n = order.len();
+
+        // a block can be in a reversed sub-sequence if its entry is its exit
+        // or if it can be run backward
+        let reversible = |(i, v):(usize, Variant)| -> bool {
+            let b = &blocks[i];
+            b.entry(v) == b.exit(v) || (options.reverse && b.can_reverse() && matches!(v, Variant::Forward | Variant::Backward))
+        };
+        let reverse = |(i, v):(usize, Variant)| -> (usize, Variant) {
+            match v {
+                Variant::Forward if blocks[i].start != blocks[i].end => (i, Variant::Backward),
+                Variant::Backward => (i, Variant::Forward),
+                _ => (i, v)
+            }
+        };
+
+        let mut improved = true;
+        let mut rounds = 0;
+        while improved && rounds < 100 {
+            improved = false;
+            rounds += 1;
+
+            for i in 0..n {
+                if !reversible(order[i]) {continue;}
+                let prev = if i == 0 {start} else {blocks[order[i-1].0].exit(order[i-1].1)};
+
+                for j in i+1..n {
+                    if !reversible(order[j]) {break;}
+
+                    let (bi, vi) = order[i];
+                    let (bj, vj) = order[j];
+                    let mut delta = prev.distance(blocks[bj].exit(vj)) - prev.distance(blocks[bi].entry(vi));
+                    if j + 1 < n {
+                        let next = blocks[order[j+1].0].entry(order[j+1].1);
+                        delta += blocks[bi].entry(vi).distance(next) - blocks[bj].exit(vj).distance(next);
+                    }
+
+                    if delta < -1e-12 {
+                        order[i..=j].reverse();
+                        for o in order[i..=j].iter_mut() {*o = reverse(*o);}
+                        improved = true;
+                    }
+                }
+            }
+        }
+
+        order
+    }
+
+    /// choose the starting vertex of each closed loop between its neighbours in the order
+    fn refine_loops(blocks:&[Block], mut order:Vec<(usize, Variant)>, start:Vec2, options:TravelOptions) -> Vec<(usize, Variant)> {
+        if !options.rotate_loops {return order;}
+
+        for k in 0..order.len() {
+            let (i, _) = order[k];
+            if !blocks[i].is_loop() {continue;}
+
+            let prev = if k == 0 {start} else {blocks[order[k-1].0].exit(order[k-1].1)};
+            let next = order.get(k+1).map(|(j, v)| blocks[*j].entry(*v));
+
+            let cost = |v:Variant| {
+                let p = blocks[i].entry(v);
+                prev.distance(p) + next.map(|n| p.distance(n)).unwrap_or(0.0)
+            };
+
+            let best = blocks[i].variants(options).into_iter()
+                .min_by(|a, b| cost(*a).total_cmp(&cost(*b))).unwrap();
+            order[k] = (i, best);
+        }
+
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::travel::*;
+
+    /// a square of side `size` cut at the depth `-1.0` from its corner `(x, y)`
+    fn square(x:f64, y:f64, size:f64, out:&mut Vec<Move>) {
+        out.push(Move::FXYmove(x, y));
+        out.push(Move::Zmove(-1.0));
+        out.push(Move::XYmove(x + size, y));
+        out.push(Move::XYmove(x + size, y + size));
+        out.push(Move::XYmove(x, y + size));
+        out.push(Move::XYmove(x, y));
+        out.push(Move::Zmove(1.0));
+    }
+
+    /// the cut segments of a path, without orientation
+    fn cuts(path:&Path) -> Vec<(i64, i64, i64, i64)> {
+        let mut position = (path.x_init, path.y_init);
+        let mut out = vec![];
+        for m in path.path.iter() {
+            match m {
+                Move::XYmove(x, y) => {
+                    let a = ((position.0 * 1e6) as i64, (position.1 * 1e6) as i64);
+                    let b = ((x * 1e6) as i64, (y * 1e6) as i64);
+                    let (a, b) = if a < b {(a, b)} else {(b, a)};
+                    out.push((a.0, a.1, b.0, b.1));
+                    position = (*x, *y);
+                },
+                Move::FXYmove(x, y) => position = (*x, *y),
+                _ => {}
+            }
+        }
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_reorder_islands() {
+        let mut moves = vec![Move::Zmove(1.0)];
+        for x in [0.0, 10.0, 2.0, 8.0, 4.0, 6.0] {
+            // each square starts at its top-right corner
+            square(x + 1.0, 1.0, -1.0, &mut moves);
+        }
+        moves.push(Move::FXYmove(0.0, 0.0));
+        let path = Path{x_init:0.0, y_init:0.0, z_init:0.0, path:moves};
+
+        let options = TravelOptions{reverse:false, rotate_loops:false};
+        let (out, report) = path.optimize_travel(options);
+
+        assert!(f64::abs(report.before - path.rapid_length()) < 1e-12);
+        assert!(f64::abs(report.after - out.rapid_length()) < 1e-12);
+        assert!(f64::abs(report.after - (f64::sqrt(2.0) + 10.0 + f64::sqrt(122.0))) < 1e-9);
+        assert_eq!(cuts(&path), cuts(&out));
+        assert_eq!(out.path.len(), path.path.len());
+        assert!(matches!(out.path.last(), Some(Move::FXYmove(x, y)) if *x == 0.0 && *y == 0.0));
+
+        // with the rotation of the loops, each square starts at its bottom-left corner
+        let (out, report) = path.optimize_travel(TravelOptions{reverse:false, rotate_loops:true});
+        assert!(f64::abs(report.after - 20.0) < 1e-9);
+        assert_eq!(cuts(&path), cuts(&out));
+    }
+
+    #[test]
+    fn test_reverse_lines() {
+        // lines from right to left, each one starting far from the end of the previous one
+        let mut moves = vec![];
+        for y in 0..5 {
+            moves.push(Move::FXYmove(10.0, y as f64));
+            moves.push(Move::Zmove(-1.0));
+            moves.push(Move::XYmove(0.0, y as f64));
+            moves.push(Move::Zmove(1.0));
+        }
+        let path = Path{x_init:0.0, y_init:0.0, z_init:1.0, path:moves};
+
+        let (_, report) = path.optimize_travel(TravelOptions{reverse:false, rotate_loops:false});
+        assert_eq!(report.before, report.after);
+
+        let (out, report) = path.optimize_travel(TravelOptions{reverse:true, rotate_loops:false});
+        assert!(f64::abs(report.after - 4.0) < 1e-9);
+        assert_eq!(cuts(&path), cuts(&out));
+
+        // a rapid move at another height makes the sub-paths dependent
+        let mut moves = path.path.clone();
+        moves[3] = Move::Zmove(0.5);
+        let path = Path{path:moves, ..path};
+        let (out, report) = path.optimize_travel(TravelOptions{reverse:true, rotate_loops:false});
+        assert_eq!(report.before, report.after);
+        assert_eq!(out.path.len(), path.path.len());
+    }
+}